@@ -0,0 +1,83 @@
+// BENCH: how long the data-line parser takes on very large lines, and how fast queries are answered
+// Each parser case is one line of about the requested size: many small items, one long STRING, one
+// long pointer, and lists nested deeply. The queries case answers a thousand generated queries over
+// a generated forest. The times are wall-clock, for comparing builds on one machine.
+use std::time::Instant;
+use super::{answer_query, check_data, check_query, parse_list, Dialect, Index, Limits, Node};
+use super::generate::{generate, Controls};
+use super::rng::Rng;
+
+// one line of about 'bytes' bytes for each case
+fn lines (bytes: usize) -> Vec<(&'static str, String)> {
+	let mut wide = "{wide: ".to_string();
+	let pieces = ["12345", "abc", "@wide", "{n: 1, x2}", "-7"];
+	let mut k = 0;
+	while wide.len() < bytes {
+		if k > 0 { wide.push_str(", "); }
+		wide.push_str(pieces[k % pieces.len()]);
+		k += 1;
+	}
+	wide.push('}');
+	let long = format!("{{s: {}}}", "a".repeat(bytes));
+	let pointer = format!("{{p: @{}}}", "b".repeat(bytes));
+	let depth = bytes / 5;
+	let deep = format!("{}1{}", "{d: ".repeat(depth), "}".repeat(depth));
+	vec![("wide", wide), ("string", long), ("pointer", pointer), ("deep", deep)]
+}
+
+fn seconds (start: Instant) -> f64 {
+	let elapsed = start.elapsed();
+	elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9
+}
+
+fn report (case: &str, what: &str, bytes: usize, start: Instant, accepted: bool) {
+	let seconds = seconds(start);
+	println!("{:<8} {:<10} {:>10} bytes {:>10.2} ms {:>10.1} MB/s  {}", case, what, bytes, seconds * 1e3,
+		bytes as f64 / 1e6 / seconds, if accepted { "accepted" } else { "REJECTED" });
+}
+
+// a thousand queries of every kind over a forest of 'bytes' / 1000 data lines (about a twentieth
+// of 'bytes' in all, since every query walks the whole forest)
+fn queries (bytes: usize) {
+	let controls = Controls { depth: 3, width: 6, pointers: 0.2, duplicates: 0.1, lines: (bytes / 1000).max(1), queries: 1000 };
+	let (document, _) = generate(&controls, &mut Rng::new(1), Dialect::new(), Limits::new()).unwrap();
+	let dot = document.iter().position(|l| l == ".").unwrap();
+	let size: usize = document[..dot].iter().map(|l| l.len() + 1).sum();
+	let data: Vec<Node> = document[..dot].iter().map(|l| check_data(l).1).collect();
+	let asked: Vec<String> = document[dot+1..document.len()-1].iter().map(|q| check_query(q).1).collect();
+	// indexing the data is part of answering
+	let start = Instant::now();
+	let index = Index::build(&data, &Dialect::new());
+	let mut answered = 0;
+	for query in &asked { answered += answer_query(&data, &index, query, &Dialect::new(), None).map(|a| a.len()).unwrap_or(0); }
+	let seconds = seconds(start);
+	println!("{:<8} {:<10} {:>10} bytes {:>10.2} ms {:>10.1} queries/s  ({} bytes of answers)", "queries", "answer", size, seconds * 1e3,
+		asked.len() as f64 / seconds, answered);
+}
+
+// wls254 bench [--bytes N] [--cases LIST]: LIST is comma-separated, from wide,string,pointer,deep,queries
+pub fn bench_command (args: &[String]) -> i32 {
+	let usage = || { eprintln!("usage: wls254 bench [--bytes N] [--cases wide,string,pointer,deep,queries]"); 2 };
+	let mut bytes = 4_000_000;
+	let mut cases: Option<Vec<String>> = None;
+	for pair in args.chunks(2) {
+		match (&*pair[0], pair.get(1)) {
+			("--bytes", Some(v)) => match v.parse::<usize>() { Ok(v) => bytes = v, Err(_) => return usage() },
+			("--cases", Some(v)) => cases = Some(v.split(',').map(|c| c.to_string()).collect()),
+			_ => return usage()
+		}
+	}
+	for (case, line) in lines(bytes) {
+		if let Some(ref wanted) = cases { if !wanted.iter().any(|c| c == case) { continue; } }
+		// the borrowed tree alone, then with the owned tree the queries run on
+		// (with no limit on depth, as --max-depth would lift it)
+		let start = Instant::now();
+		let accepted = parse_list(&line, &Dialect::new(), usize::MAX).is_ok();
+		report(case, "parse", line.len(), start, accepted);
+		let start = Instant::now();
+		let accepted = parse_list(&line, &Dialect::new(), usize::MAX).map(|list| list.to_node()).is_ok();
+		report(case, "check_data", line.len(), start, accepted);
+	}
+	if cases.as_ref().map(|wanted| wanted.iter().any(|c| c == "queries")).unwrap_or(true) { queries(bytes); }
+	0
+}
@@ -0,0 +1,352 @@
+// Numbers of any size, just enough for SUM: parse a NUMBER, add exactly, compare, and print
+// A BigInt's magnitude is kept in base 10^9 limbs, least significant first, with no zero limbs on
+// top (so zero is no limbs at all, and is never negative). A Decimal is a BigInt times a power of ten.
+use std::cmp::Ordering;
+use std::fmt;
+
+const BASE: u64 = 1_000_000_000;
+
+// the largest exponent (either way) a NUMBER may be written with, so no literal can make SUM
+// work with numbers millions of digits long
+pub const MAX_EXPONENT: i64 = 1000;
+
+#[derive(Clone)]
+pub struct BigInt {
+	negative: bool,
+	limbs: Vec<u32>
+}
+
+impl BigInt {
+	pub fn zero () -> BigInt {
+		BigInt { negative: false, limbs: vec![] }
+	}
+
+	// [-+]?[0-9]+, the NUMBERs check_numeric takes without the 64-bit bound
+	pub fn parse (s: &str) -> Option<BigInt> {
+		let (negative, digits) = match s.as_bytes().first() {
+			Some(&b'-') => (true, &s[1..]),
+			Some(&b'+') => (false, &s[1..]),
+			_ => (false, s)
+		};
+		if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) { return None; }
+		let mut limbs: Vec<u32> = vec![];
+		let mut end = digits.len();
+		while end > 0 {
+			let start = end.saturating_sub(9);
+			limbs.push(digits[start..end].parse::<u32>().unwrap());
+			end = start;
+		}
+		let mut n = BigInt { negative, limbs };
+		n.trim();
+		Some(n)
+	}
+
+	pub fn from_i128 (n: i128) -> BigInt {
+		let mut magnitude = if n < 0 { (n as u128).wrapping_neg() } else { n as u128 };
+		let mut limbs: Vec<u32> = vec![];
+		while magnitude > 0 {
+			limbs.push((magnitude % BASE as u128) as u32);
+			magnitude /= BASE as u128;
+		}
+		BigInt { negative: n < 0, limbs }
+	}
+
+	// the value as an i64, if it is in range
+	pub fn to_i64 (&self) -> Option<i64> {
+		if self.limbs.len() > 3 { return None; }
+		let mut magnitude: i128 = 0;
+		for &limb in self.limbs.iter().rev() { magnitude = magnitude * BASE as i128 + limb as i128; }
+		let n = if self.negative { -magnitude } else { magnitude };
+		if n < i64::MIN as i128 || n > i64::MAX as i128 { return None; }
+		Some(n as i64)
+	}
+
+	fn is_zero (&self) -> bool { self.limbs.is_empty() }
+
+	// self * m + a, on the magnitude
+	fn mul_small_add (&mut self, m: u32, a: u32) {
+		let mut carry = a as u64;
+		for limb in self.limbs.iter_mut() {
+			let product = *limb as u64 * m as u64 + carry;
+			*limb = (product % BASE) as u32;
+			carry = product / BASE;
+		}
+		while carry > 0 { self.limbs.push((carry % BASE) as u32); carry /= BASE; }
+		self.trim();
+	}
+
+	// self * 10^k
+	fn shift (&mut self, k: usize) {
+		if self.is_zero() { return; }
+		let mut limbs = vec![0; k / 9];
+		limbs.append(&mut self.limbs);
+		self.limbs = limbs;
+		for _ in 0..k % 9 { self.mul_small_add(10, 0); }
+	}
+
+	// divide by ten as often as it goes evenly, and say how often that was
+	fn strip_tens (&mut self) -> usize {
+		if self.is_zero() { return 0; }
+		let mut count = 0;
+		while self.limbs[0] == 0 { self.limbs.remove(0); count += 9; }
+		while self.limbs[0].is_multiple_of(10) {
+			let mut remainder = 0;
+			for limb in self.limbs.iter_mut().rev() {
+				let value = remainder * BASE + *limb as u64;
+				*limb = (value / 10) as u32;
+				remainder = value % 10;
+			}
+			self.trim();
+			count += 1;
+		}
+		count
+	}
+
+	pub fn cmp (&self, other: &BigInt) -> Ordering {
+		match (self.negative, other.negative) {
+			(false, true) => Ordering::Greater,
+			(true, false) => Ordering::Less,
+			(false, false) => compare(&self.limbs, &other.limbs),
+			(true, true) => compare(&other.limbs, &self.limbs)
+		}
+	}
+
+	pub fn add (&mut self, other: &BigInt) {
+		if self.negative == other.negative {
+			let mut carry = 0;
+			for i in 0..other.limbs.len().max(self.limbs.len()) {
+				if i == self.limbs.len() { self.limbs.push(0); }
+				let sum = self.limbs[i] as u64 + *other.limbs.get(i).unwrap_or(&0) as u64 + carry;
+				self.limbs[i] = (sum % BASE) as u32;
+				carry = sum / BASE;
+			}
+			if carry > 0 { self.limbs.push(carry as u32); }
+			return;
+		}
+		// opposite signs: take the smaller magnitude from the larger, keeping the larger's sign
+		let (larger, smaller, negative) = match compare(&self.limbs, &other.limbs) {
+			Ordering::Less => (&other.limbs, &self.limbs, other.negative),
+			_ => (&self.limbs, &other.limbs, self.negative)
+		};
+		let mut limbs: Vec<u32> = vec![];
+		let mut borrow = 0;
+		for (i, &limb) in larger.iter().enumerate() {
+			let take = *smaller.get(i).unwrap_or(&0) as i64 + borrow;
+			let mut limb = limb as i64 - take;
+			borrow = 0;
+			if limb < 0 { limb += BASE as i64; borrow = 1; }
+			limbs.push(limb as u32);
+		}
+		*self = BigInt { negative, limbs };
+		self.trim();
+	}
+
+	fn trim (&mut self) {
+		while self.limbs.last() == Some(&0) { self.limbs.pop(); }
+		if self.limbs.is_empty() { self.negative = false; }
+	}
+}
+
+// which of two magnitudes is larger
+fn compare (a: &[u32], b: &[u32]) -> Ordering {
+	if a.len() != b.len() { return a.len().cmp(&b.len()); }
+	for i in (0..a.len()).rev() {
+		if a[i] != b[i] { return a[i].cmp(&b[i]); }
+	}
+	Ordering::Equal
+}
+
+impl fmt::Display for BigInt {
+	fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let top = match self.limbs.last() { Some(top) => top, None => return write!(f, "0") };
+		write!(f, "{}{}", if self.negative { "-" } else { "" }, top)?;
+		for limb in self.limbs.iter().rev().skip(1) { write!(f, "{:09}", limb)?; }
+		Ok(())
+	}
+}
+
+// NOTATION: the ways of writing a NUMBER past the assignment's '-'? [0-9]+ (--numbers)
+//   NUMBER   ::= SIGN? (HEX | DIGITS FRACTION? EXPONENT?)      SIGN ::= '-' | '+'
+//   DIGITS   ::= [0-9]+, or with separators [0-9] ('_'? [0-9])*
+//   FRACTION ::= '.' DIGITS                                    (decimal)
+//   EXPONENT ::= [eE] SIGN? [0-9]+, at most MAX_EXPONENT       (exponent)
+//   HEX      ::= '0' [xX] [0-9a-fA-F]+, with '_' as in DIGITS   (hex)
+#[derive(Clone, Copy, PartialEq)]
+pub struct Notation {
+	pub decimal: bool,
+	pub exponent: bool,
+	pub hex: bool,
+	pub separators: bool
+}
+
+impl Notation {
+	pub fn plain () -> Notation {
+		Notation { decimal: false, exponent: false, hex: false, separators: false }
+	}
+
+	// whether a NUMBER can be written more than one way, so NUMBERs compare by value
+	pub fn extended (&self) -> bool {
+		*self != Notation::plain()
+	}
+
+	// the NUMBER token class for the bundled grammar, as a pattern
+	pub fn pattern (&self) -> String {
+		let run = |digit: &str| if self.separators { format!("{}(_?{})*", digit, digit) } else { format!("{}+", digit) };
+		let mut number = run("[0-9]");
+		if self.decimal { number = format!("{}(\\.{})?", number, run("[0-9]")); }
+		if self.exponent { number.push_str("([eE][-+]?[0-9]+)?"); }
+		if self.hex { number = format!("0[xX]{}|{}", run("[0-9a-fA-F]"), number); }
+		format!("[-+]?({})", number)
+	}
+
+	// the digits of a DIGITS or hex run in 's', without separators
+	fn digits (&self, s: &str, radix: u32) -> Option<String> {
+		let mut digits = String::new();
+		let mut after_digit = false;
+		for c in s.chars() {
+			if c == '_' && self.separators && after_digit { after_digit = false; continue; }
+			if !c.is_digit(radix) { return None; }
+			digits.push(c);
+			after_digit = true;
+		}
+		if !after_digit { return None; }
+		Some(digits)
+	}
+}
+
+#[derive(Clone)]
+pub struct Decimal {
+	coefficient: BigInt,
+	exponent: i64
+}
+
+impl Decimal {
+	pub fn zero () -> Decimal {
+		Decimal { coefficient: BigInt::zero(), exponent: 0 }
+	}
+
+	pub fn from_i128 (n: i128) -> Decimal {
+		Decimal { coefficient: BigInt::from_i128(n), exponent: 0 }
+	}
+
+	// a NUMBER as 'notation' allows it to be written
+	pub fn parse (s: &str, notation: &Notation) -> Option<Decimal> {
+		let (negative, rest) = match s.as_bytes().first() {
+			Some(&b'-') => (true, &s[1..]),
+			Some(&b'+') => (false, &s[1..]),
+			_ => (false, s)
+		};
+		let mut number = if notation.hex && (rest.starts_with("0x") || rest.starts_with("0X")) {
+			let mut coefficient = BigInt::zero();
+			for c in notation.digits(&rest[2..], 16)?.chars() { coefficient.mul_small_add(16, c.to_digit(16).unwrap()); }
+			Decimal { coefficient, exponent: 0 }
+		} else {
+			let (mantissa, exponent) = match rest.find(['e', 'E']) {
+				Some(at) if notation.exponent => {
+					let written = &rest[at+1..];
+					let digits = written.trim_start_matches(['-', '+']);
+					if written.len() - digits.len() > 1 || digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) { return None; }
+					let exponent = digits.parse::<i64>().ok().filter(|&e| e <= MAX_EXPONENT)?;
+					(&rest[..at], if written.starts_with('-') { -exponent } else { exponent })
+				},
+				_ => (rest, 0)
+			};
+			let (whole, fraction) = match mantissa.find('.') {
+				Some(at) if notation.decimal => (&mantissa[..at], notation.digits(&mantissa[at+1..], 10)?),
+				_ => (mantissa, String::new())
+			};
+			let digits = notation.digits(whole, 10)? + &*fraction;
+			Decimal { coefficient: BigInt::parse(&digits)?, exponent: exponent - fraction.len() as i64 }
+		};
+		number.coefficient.negative = negative && !number.coefficient.is_zero();
+		Some(number)
+	}
+
+	pub fn add (&mut self, other: &Decimal) {
+		if self.exponent > other.exponent {
+			self.coefficient.shift((self.exponent - other.exponent) as usize);
+			self.exponent = other.exponent;
+		}
+		if other.exponent > self.exponent {
+			let mut aligned = other.coefficient.clone();
+			aligned.shift((other.exponent - self.exponent) as usize);
+			self.coefficient.add(&aligned);
+		} else {
+			self.coefficient.add(&other.coefficient);
+		}
+	}
+
+	// whether the value lies in the 64-bit integer range (it need not be whole)
+	pub fn within_i64 (&self) -> bool {
+		if self.exponent >= 0 {
+			let mut whole = self.coefficient.clone();
+			whole.shift(self.exponent as usize);
+			return whole.to_i64().is_some();
+		}
+		let (mut low, mut high) = (BigInt::from_i128(i64::MIN as i128), BigInt::from_i128(i64::MAX as i128));
+		low.shift(-self.exponent as usize);
+		high.shift(-self.exponent as usize);
+		self.coefficient.cmp(&low) != Ordering::Less && self.coefficient.cmp(&high) != Ordering::Greater
+	}
+
+	// the same text for every way of writing the same value: the coefficient without trailing
+	// zeros, and the exponent that goes with it
+	pub fn key (&self) -> String {
+		let mut coefficient = self.coefficient.clone();
+		let tens = coefficient.strip_tens() as i64;
+		if coefficient.is_zero() { return "0".to_string(); }
+		format!("{}e{}", coefficient, self.exponent + tens)
+	}
+}
+
+// in plain decimal notation, with no exponent and no trailing zeros after the point
+impl fmt::Display for Decimal {
+	fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let mut coefficient = self.coefficient.clone();
+		let exponent = self.exponent + coefficient.strip_tens() as i64;
+		let negative = coefficient.negative;
+		coefficient.negative = false;
+		if coefficient.is_zero() { return write!(f, "0"); }
+		let digits = coefficient.to_string();
+		if exponent >= 0 {
+			return write!(f, "{}{}{}", if negative { "-" } else { "" }, digits, "0".repeat(exponent as usize));
+		}
+		let point = -exponent as usize;
+		let digits = if digits.len() <= point { "0".repeat(point + 1 - digits.len()) + &*digits } else { digits };
+		let (whole, fraction) = digits.split_at(digits.len() - point);
+		write!(f, "{}{}.{}", if negative { "-" } else { "" }, whole, fraction)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn big_integers_add_exactly_across_limbs_and_signs () {
+		let mut n = BigInt::parse("999999999999999999").unwrap();
+		n.add(&BigInt::parse("+1").unwrap());
+		assert_eq!(format!("{}", n), "1000000000000000000");
+		n.add(&BigInt::parse("-1000000000000000001").unwrap());
+		assert_eq!((format!("{}", n), n.to_i64()), ("-1".to_string(), Some(-1)));
+		n.add(&BigInt::from_i128(1));
+		assert_eq!(format!("{}", n), "0");
+		assert_eq!(BigInt::from_i128(i64::MAX as i128 + 1).to_i64(), None);
+		assert_eq!(BigInt::parse("-0").map(|n| format!("{}", n)), Some("0".to_string()));
+		assert!(BigInt::parse("1-2").is_none() && BigInt::parse("-").is_none());
+	}
+
+	#[test]
+	fn decimals_in_every_notation_have_one_key_per_value () {
+		let all = Notation { decimal: true, exponent: true, hex: true, separators: true };
+		let keys: Vec<String> = ["1", "1.0", "+1e0", "0x1", "10e-1", "0_1"].iter().map(|s| Decimal::parse(s, &all).unwrap().key()).collect();
+		assert!(keys.iter().all(|k| *k == keys[0]), "{:?}", keys);
+		for s in &["1.", ".5", "1e", "0x", "1__0", "1e1001"] { assert!(Decimal::parse(s, &all).is_none(), "{}", s); }
+		assert!(Decimal::parse("1.5", &Notation::plain()).is_none() && Decimal::parse("1e2", &Notation { exponent: false, ..all }).is_none());
+		let mut sum = Decimal::parse("0.1", &all).unwrap();
+		sum.add(&Decimal::parse("0.2", &all).unwrap());
+		sum.add(&Decimal::parse("-2.5e-3", &all).unwrap());
+		assert_eq!(format!("{}", sum), "0.2975");
+		assert_eq!(format!("{}", Decimal::parse("-12e3", &all).unwrap()), "-12000");
+	}
+}
@@ -0,0 +1,218 @@
+// Chomsky normal form and CYK membership testing
+// to_cnf rewrites a grammar so every alternative is A ::= B C or A ::= terminal (plus S ::= %empty
+// for a new start symbol S when the language has the empty sentence), by the usual steps: a new
+// start symbol, terminals out of long alternatives, long alternatives split in two, %empty
+// alternatives and then unit alternatives removed. cyk then fills the triangular table of which
+// nonterminals derive each span of the input.
+use std::collections::{BTreeSet, HashSet};
+use super::grammar::{Grammar, Symbol, Token};
+use super::hygiene::{fresh, grammar_of, productive, remove_useless, rules_of};
+
+pub fn to_cnf (grammar: &Grammar) -> Result<Grammar, String> {
+	if !productive(grammar).contains(&grammar.start) {
+		return Err(format!("the start symbol {} derives no terminal string, so the language is empty", grammar.start));
+	}
+	let (mut order, mut alternatives) = rules_of(grammar);
+
+	// START: a new start symbol that no alternative refers to
+	let start = fresh(&format!("{}_0", grammar.start), &order);
+	order.insert(0, start.clone());
+	alternatives.insert(start.clone(), vec![vec![Symbol::Nonterminal(grammar.start.clone())]]);
+	let mut base = grammar.clone();
+	base.start = start.clone();
+
+	// TERM: in alternatives of two or more symbols, each terminal becomes a nonterminal deriving just it
+	let mut terminals: Vec<(Symbol, String)> = vec![];
+	for nonterminal in order.clone() {
+		let mut alts = alternatives.remove(&nonterminal).unwrap();
+		for rhs in alts.iter_mut() {
+			if rhs.len() < 2 { continue; }
+			for symbol in rhs.iter_mut() {
+				if let Symbol::Nonterminal(_) = *symbol { continue; }
+				let name = match terminals.iter().find(|t| t.0 == *symbol) {
+					Some((_, name)) => name.clone(),
+					None => {
+						let base = match *symbol {
+							Symbol::Class(ref name) => format!("T_{}", name),
+							Symbol::Literal(ref text) if text.chars().all(|c| c.is_ascii_alphanumeric()) => format!("T_{}", text),
+							_ => format!("T_{}", terminals.len() + 1)
+						};
+						let name = fresh(&base, &order);
+						order.push(name.clone());
+						alternatives.insert(name.clone(), vec![vec![symbol.clone()]]);
+						terminals.push((symbol.clone(), name.clone()));
+						name
+					}
+				};
+				*symbol = Symbol::Nonterminal(name);
+			}
+		}
+		alternatives.insert(nonterminal, alts);
+	}
+
+	// BIN: A ::= X1 X2 ... Xn becomes A ::= X1 A_1, A_1 ::= X2 A_2, ..., A_n-2 ::= Xn-1 Xn
+	for position in (0..order.len()).rev() {
+		let a = order[position].clone();
+		let mut added: Vec<String> = vec![];
+		let mut rewritten: Vec<Vec<Symbol>> = vec![];
+		for rhs in alternatives[&a].clone() {
+			if rhs.len() <= 2 { rewritten.push(rhs); continue; }
+			let mut lhs: Option<String> = None;
+			for symbol in &rhs[..rhs.len() - 2] {
+				let next = fresh(&format!("{}_{}", a, added.len() + 1), &order.iter().chain(added.iter()).cloned().collect::<Vec<String>>());
+				added.push(next.clone());
+				let pair = vec![symbol.clone(), Symbol::Nonterminal(next.clone())];
+				match lhs { None => rewritten.push(pair), Some(ref name) => { alternatives.insert(name.clone(), vec![pair]); } }
+				lhs = Some(next);
+			}
+			alternatives.insert(lhs.unwrap(), vec![rhs[rhs.len()-2..].to_vec()]);
+		}
+		alternatives.insert(a, rewritten);
+		for (n, name) in added.into_iter().enumerate() { order.insert(position + 1 + n, name); }
+	}
+
+	// DEL: every alternative also appears with each subset of its nullable nonterminals left out,
+	// and the %empty alternatives go, except the new start symbol's
+	let nullable = grammar_of(&base, (order.clone(), alternatives.clone())).nullable();
+	for nonterminal in &order {
+		let mut rewritten: Vec<Vec<Symbol>> = vec![];
+		for rhs in &alternatives[nonterminal] {
+			let mut variants: Vec<Vec<Symbol>> = vec![vec![]];
+			for symbol in rhs {
+				let skippable = match *symbol { Symbol::Nonterminal(ref n) => nullable.contains(n), _ => false };
+				let mut more: Vec<Vec<Symbol>> = vec![];
+				for variant in &variants {
+					if skippable { more.push(variant.clone()); }
+					let mut with = variant.clone();
+					with.push(symbol.clone());
+					more.push(with);
+				}
+				variants = more;
+			}
+			for variant in variants {
+				if variant.is_empty() && *nonterminal != start { continue; }
+				if !rewritten.contains(&variant) { rewritten.push(variant); }
+			}
+		}
+		alternatives.insert(nonterminal.clone(), rewritten);
+	}
+
+	// UNIT: A ::= B is replaced by B's alternatives (through any chain of unit alternatives)
+	let unit = |rhs: &Vec<Symbol>| -> Option<String> {
+		match (rhs.len(), rhs.first()) { (1, Some(Symbol::Nonterminal(n))) => Some(n.clone()), _ => None }
+	};
+	let mut rewritten_all: Vec<(String, Vec<Vec<Symbol>>)> = vec![];
+	for nonterminal in &order {
+		let mut reached: Vec<String> = vec![nonterminal.clone()];
+		let mut i = 0;
+		while i < reached.len() {
+			for rhs in &alternatives[&reached[i]] {
+				if let Some(next) = unit(rhs) { if !reached.contains(&next) { reached.push(next); } }
+			}
+			i += 1;
+		}
+		let mut rewritten: Vec<Vec<Symbol>> = vec![];
+		for name in &reached {
+			for rhs in &alternatives[name] {
+				if unit(rhs).is_none() && !rewritten.contains(rhs) { rewritten.push(rhs.clone()); }
+			}
+		}
+		rewritten_all.push((nonterminal.clone(), rewritten));
+	}
+	for (nonterminal, rewritten) in rewritten_all { alternatives.insert(nonterminal, rewritten); }
+	remove_useless(&grammar_of(&base, (order, alternatives)))
+}
+
+// the CYK table: cells[length - 1][start] holds the nonterminals that derive that span
+pub struct Table {
+	pub cells: Vec<Vec<BTreeSet<String>>>
+}
+
+// CYK: which nonterminals of the CNF grammar derive each span of 'tokens', and whether the whole input is a sentence
+pub fn cyk (cnf: &Grammar, tokens: &[Token]) -> (bool, Table) {
+	let n = tokens.len();
+	let mut cells: Vec<Vec<BTreeSet<String>>> = (0..n).map(|l| vec![BTreeSet::new(); n - l]).collect();
+	for (i, token) in tokens.iter().enumerate() {
+		for production in &cnf.productions {
+			if production.rhs.len() == 1 && production.rhs[0] == token.kind { cells[0][i].insert(production.lhs.clone()); }
+		}
+	}
+	let binary: Vec<(&str, &str, &str)> = cnf.productions.iter().filter_map(|p| match (p.rhs.first(), p.rhs.get(1)) {
+		(Some(Symbol::Nonterminal(b)), Some(Symbol::Nonterminal(c))) => Some((&*p.lhs, &**b, &**c)),
+		_ => None
+	}).collect();
+	for length in 2..n+1 {
+		for i in 0..n - length + 1 {
+			let mut found: HashSet<&str> = HashSet::new();
+			for split in 1..length {
+				let (left, right) = (&cells[split-1][i], &cells[length-split-1][i+split]);
+				for &(a, b, c) in &binary { if left.contains(b) && right.contains(c) { found.insert(a); } }
+			}
+			cells[length-1][i] = found.into_iter().map(|a| a.to_string()).collect();
+		}
+	}
+	let accepted = if n == 0 {
+		cnf.alternatives(&cnf.start).iter().any(|&p| cnf.productions[p].rhs.is_empty())
+	} else { cells[n-1][0].contains(&cnf.start) };
+	(accepted, Table { cells })
+}
+
+impl Table {
+	// the triangle with the longest span on top and the tokens along the bottom, columns padded to line up
+	pub fn render (&self, tokens: &[Token]) -> String {
+		let n = tokens.len();
+		let cell = |length: usize, i: usize| -> String {
+			let names: Vec<&str> = self.cells[length-1][i].iter().map(|s| &**s).collect();
+			if names.is_empty() { "-".to_string() } else { names.join(",") }
+		};
+		let mut widths: Vec<usize> = tokens.iter().map(|t| t.text.chars().count()).collect();
+		for length in 1..n+1 {
+			for (i, width) in widths.iter_mut().enumerate().take(n - length + 1) { *width = (*width).max(cell(length, i).chars().count()); }
+		}
+		let label = format!("{}", n).len();
+		let mut out = String::new();
+		for length in (1..n+1).rev() {
+			let row: Vec<String> = (0..n - length + 1).map(|i| format!("{:width$}", cell(length, i), width = widths[i])).collect();
+			out.push_str(&format!("{:>label$}  {}\n", length, row.join("  ").trim_end(), label = label));
+		}
+		let bottom: Vec<String> = tokens.iter().enumerate().map(|(i, t)| format!("{:width$}", t.text, width = widths[i])).collect();
+		out.push_str(&format!("{:>label$}  {}\n", "", bottom.join("  ").trim_end(), label = label));
+		out
+	}
+}
+
+// wls254 cnf [--grammar FILE]: print the grammar in Chomsky normal form
+pub fn cnf_command (args: &[String]) -> i32 {
+	let grammar = match args.len() {
+		0 => Grammar::data(),
+		2 if args[0] == "--grammar" => match Grammar::load(&args[1]) { Ok(g) => g, Err(message) => { eprintln!("{}", message); return 2; } },
+		_ => { eprintln!("usage: wls254 cnf [--grammar FILE]"); return 2; }
+	};
+	match to_cnf(&grammar) {
+		Ok(cnf) => { print!("{}", cnf); 0},
+		Err(message) => { eprintln!("{}", message); 1}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn cnf_of_the_data_grammar_recognizes_data_lines () {
+		let grammar = Grammar::data();
+		let cnf = to_cnf(&grammar).unwrap();
+		for p in &cnf.productions {
+			let binary = p.rhs.len() == 2 && p.rhs.iter().all(|s| matches!(*s, Symbol::Nonterminal(_)));
+			let terminal = p.rhs.len() == 1 && !matches!(p.rhs[0], Symbol::Nonterminal(_));
+			assert!(binary || terminal || (p.rhs.is_empty() && p.lhs == cnf.start), "{} is not in CNF", p);
+		}
+		let lines = [("{a: 1, @b, {c: x}}", true), ("{a: 1,}", false), ("{a 1}", false), ("{a: {b: {c: 1}, 2}}", false)];
+		for &(line, accepted) in &lines {
+			let tokens = grammar.tokenize(line).unwrap();
+			let (verdict, table) = cyk(&cnf, &tokens);
+			assert_eq!(verdict, accepted, "{}", line);
+			assert!(table.render(&tokens).lines().all(|row| !row.ends_with(' ')));
+		}
+	}
+}
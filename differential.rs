@@ -0,0 +1,114 @@
+// DIFFERENTIAL: every parsing engine must agree on which inputs are sentences of a grammar
+// Each round samples a random sentence (which every engine must accept), then a few near misses
+// made from it by dropping, repeating, swapping or replacing a token. Engines that cannot take the
+// grammar (backtracking on left recursion, LL(1) and LALR(1) on conflicts) sit the check out; for
+// the bundled grammar the hand-written check_data takes part too.
+use super::check_data;
+use super::cyk::{cyk, to_cnf};
+use super::earley::Earley;
+use super::grammar::Grammar;
+use super::lalr::{self, Automaton};
+use super::ll1::{self, Ll1};
+use super::rng::Rng;
+
+// whether one engine takes 'input' as a sentence
+fn verdict (engine: &str, grammar: &Grammar, cnf: &Grammar, input: &str) -> bool {
+	match engine {
+		"backtrack" => grammar.parse_input(input).is_ok(),
+		"earley" => Earley::run(grammar, input).map(|e| e.accepted()).unwrap_or(false),
+		"ll1" => ll1::parse(grammar, input).is_ok(),
+		"lalr" => lalr::parse(grammar, input).is_ok(),
+		"cyk" => grammar.tokenize(input).map(|tokens| cyk(cnf, &tokens).0).unwrap_or(false),
+		_ => check_data(input).0
+	}
+}
+
+// the engines that took part, or a description of the first disagreement
+pub fn check_engines (grammar: &Grammar, hand: bool, seed: u64, rounds: u64) -> Result<Vec<&'static str>, String> {
+	let cnf = to_cnf(grammar)?;
+	let mut engines: Vec<&'static str> = vec![];
+	if grammar.left_recursive().is_none() { engines.push("backtrack"); }
+	engines.push("earley");
+	if Ll1::build(grammar).conflicts(grammar).is_empty() { engines.push("ll1"); }
+	if Automaton::build(grammar).conflicts().is_empty() { engines.push("lalr"); }
+	engines.push("cyk");
+	if hand { engines.push("check_data"); }
+
+	// any terminal can replace a token in a near miss
+	let literals = grammar.literals();
+	for round in 0..rounds {
+		let mut rng = Rng::new(seed.wrapping_add(round));
+		let context = format!("seed {}", seed.wrapping_add(round));
+		let sentence = grammar.sample(&mut rng, 6).ok_or(format!("the start symbol {} derives nothing", grammar.start))?;
+		let input = sentence.join(" ");
+		for &engine in &engines {
+			if !verdict(engine, grammar, &cnf, &input) { return Err(format!("{}: {} rejects the generated sentence {:?}", context, engine, input)); }
+		}
+		for _ in 0..3 {
+			let mut tokens = sentence.clone();
+			let n = tokens.len();
+			match rng.below(4) {
+				0 if n > 0 => { tokens.remove(rng.below(n)); },
+				1 if n > 0 => { let at = rng.below(n); let token = tokens[at].clone(); tokens.insert(at, token); },
+				2 if n > 1 => { let at = rng.below(n - 1); tokens.swap(at, at + 1); },
+				_ if literals.len() + grammar.classes.len() > 0 => {
+					let pick = rng.below(literals.len() + grammar.classes.len());
+					let token = if pick < literals.len() { literals[pick].clone() } else { grammar.classes[pick - literals.len()].sample(&mut rng) };
+					let at = rng.below(n + 1);
+					if at < n { tokens[at] = token; } else { tokens.push(token); }
+				},
+				_ => {}
+			}
+			// tokens are run together or spaced at random, so the hand-written parser sees both
+			let mut mutant = String::new();
+			for (k, token) in tokens.iter().enumerate() {
+				if k > 0 && rng.below(2) == 0 { mutant.push(' '); }
+				mutant.push_str(token);
+			}
+			let verdicts: Vec<bool> = engines.iter().map(|e| verdict(e, grammar, &cnf, &mutant)).collect();
+			if verdicts.iter().any(|&v| v != verdicts[0]) {
+				let accepted: Vec<&str> = engines.iter().zip(&verdicts).filter(|p| *p.1).map(|p| *p.0).collect();
+				let rejected: Vec<&str> = engines.iter().zip(&verdicts).filter(|p| !*p.1).map(|p| *p.0).collect();
+				return Err(format!("{}: engines disagree on {:?}: accepted by {}, rejected by {}", context, mutant, accepted.join(" "), rejected.join(" ")));
+			}
+		}
+	}
+	Ok(engines)
+}
+
+// wls254 check-engines [--grammar FILE] [--seed N] [--rounds N]
+pub fn check_engines_command (args: &[String]) -> i32 {
+	let usage = || { eprintln!("usage: wls254 check-engines [--grammar FILE] [--seed N] [--rounds N]"); 2 };
+	let (mut seed, mut rounds) = (1, 200);
+	let mut grammar_file: Option<String> = None;
+	for pair in args.chunks(2) {
+		let value = pair.get(1).and_then(|v| v.parse::<u64>().ok());
+		match (&*pair[0], value) {
+			("--seed", Some(v)) => seed = v,
+			("--rounds", Some(v)) => rounds = v,
+			("--grammar", _) if pair.len() == 2 => grammar_file = Some(pair[1].clone()),
+			_ => return usage()
+		}
+	}
+	let grammar = match grammar_file {
+		Some(ref path) => match Grammar::load(path) { Ok(g) => g, Err(message) => { eprintln!("{}", message); return 2; } },
+		None => Grammar::data()
+	};
+	match check_engines(&grammar, grammar_file.is_none(), seed, rounds) {
+		Ok(engines) => { println!("ok: {} agree over {} rounds from seed {}", engines.join(" "), rounds, seed); 0},
+		Err(message) => { eprintln!("{}", message); 1}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn every_engine_agrees_with_check_data () {
+		assert_eq!(check_engines(&Grammar::data(), true, 1, 200), Ok(vec!["backtrack", "earley", "ll1", "lalr", "cyk", "check_data"]));
+		// a left-recursive, ambiguous grammar leaves out the engines that cannot take it
+		let grammar = Grammar::parse("E ::= E '+' E | E '*' E | N\nN = /[0-9]+/\n", "amb.grammar").unwrap();
+		assert_eq!(check_engines(&grammar, false, 1, 50), Ok(vec!["earley", "cyk"]));
+	}
+}
@@ -0,0 +1,519 @@
+// An open document for editor use: each line's parse result is kept between edits, so a change
+// only reparses the lines it touched. Data lines are independent of each other; only the section a
+// line falls in and the NAMECHECK/PTRS checks depend on the rest of the document. The name and
+// pointer counts those checks need, and the analysis an editor is shown, are patched as lines come
+// and go instead of being rebuilt: an edit redoes the lines it replaced, the lines after them whose
+// section it changed, and the warnings of names it made (or stopped being) defined or duplicated.
+// A document containing a "." line is checked as a whole input (data, ".", queries, QUIT);
+// anything else is checked as a data file, the way --data files are read
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use super::{check_quoted, check_include, include_file, list_names, parse_list, parse_query, Dialect, InputState, Limits, Mode, Section};
+
+// a list name or pointer target, and the columns (UTF-16 units, as LSP counts them) it covers
+#[derive(PartialEq, Debug)]
+pub struct Symbol {
+	pub name: String,
+	pub line: usize,
+	pub start: usize,
+	pub end: usize
+}
+
+// something to report on a line: severity 1 is an error, 2 a warning
+#[derive(PartialEq, Debug)]
+pub struct Problem {
+	pub line: usize,
+	pub start: usize,
+	pub end: usize,
+	pub severity: usize,
+	pub message: String
+}
+
+// everything an editor needs to know about the document, each list in line order
+#[derive(PartialEq, Debug)]
+pub struct Analysis {
+	pub problems: Vec<Problem>,
+	pub names: Vec<Symbol>,
+	pub pointers: Vec<Symbol>,
+	// names of the lists brought in by INCLUDE lines, with the line that brings each in
+	pub included: Vec<(usize, String)>
+}
+
+// an entry of the analysis, which knows the line it is on
+trait Lined {
+	fn line (&self) -> usize;
+	fn renumber (&mut self, moved: isize);
+}
+
+impl Lined for Problem {
+	fn line (&self) -> usize { self.line }
+	fn renumber (&mut self, moved: isize) { self.line = (self.line as isize + moved) as usize; }
+}
+
+impl Lined for Symbol {
+	fn line (&self) -> usize { self.line }
+	fn renumber (&mut self, moved: isize) { self.line = (self.line as isize + moved) as usize; }
+}
+
+impl Lined for (usize, String) {
+	fn line (&self) -> usize { self.0 }
+	fn renumber (&mut self, moved: isize) { self.0 = (self.0 as isize + moved) as usize; }
+}
+
+// put 'entries' in place of those for lines from..to, in a list kept in line order
+fn replace_lines<T: Lined> (list: &mut Vec<T>, from: usize, to: usize, entries: Vec<T>) {
+	let lo = list.partition_point(|e| e.line() < from);
+	let hi = list.partition_point(|e| e.line() < to);
+	list.splice(lo..hi, entries);
+}
+
+// drop the entries for lines from..to, and move the ones after them 'moved' lines on
+fn remove_lines<T: Lined> (list: &mut Vec<T>, from: usize, to: usize, moved: isize) {
+	replace_lines(list, from, to, vec![]);
+	if moved == 0 { return; }
+	let after = list.partition_point(|e| e.line() < from);
+	for entry in &mut list[after..] { entry.renumber(moved); }
+}
+
+// the characters a list name or pointer target can be written with, in any dialect: everything
+// the data-line parser does not stop a word at (the dialect then says which words are STRINGs)
+pub fn is_name_char (c: char) -> bool {
+	!c.is_whitespace() && !"{}:,=@\"".contains(c)
+}
+
+// UTF-16 column of every char boundary in 'line', plus one past the end
+pub fn utf16_columns (line: &str) -> Vec<usize> {
+	let mut columns = vec![0];
+	let mut col = 0;
+	for c in line.chars() { col += c.len_utf16(); columns.push(col); }
+	columns
+}
+
+// byte offset of UTF-16 column 'col' in 'line' (clamped to the end of the line)
+fn byte_offset (line: &str, col: usize) -> usize {
+	let mut units = 0;
+	for (offset, c) in line.char_indices() {
+		if units >= col { return offset; }
+		units += c.len_utf16();
+	}
+	line.len()
+}
+
+// names found in a line, each as (name, start, end)
+type Spans = Vec<(String, usize, usize)>;
+
+// the list names (after '{') and pointer targets (after '@') in a valid data line, as (name, start, end)
+// a name is given in the form the dialect stores it in (so in NFC with --strings unicode), and any
+// QSTRING is skipped whole, braces and all
+fn scan_line (line: &str, dialect: &Dialect) -> (Spans, Spans) {
+	let mut names: Spans = vec![];
+	let mut pointers: Spans = vec![];
+	let chars: Vec<char> = line.chars().collect();
+	let offsets: Vec<usize> = line.char_indices().map(|c| c.0).collect();
+	let columns = utf16_columns(line);
+	let mut i = 0;
+	while i < chars.len() {
+		if chars[i] == '"' && dialect.quoted {
+			let end = offsets[i] + check_quoted(&line[offsets[i]..]).map(|q| q.0).unwrap_or(1);
+			while i < chars.len() && offsets[i] < end { i += 1; }
+		} else if chars[i] == '{' || chars[i] == '@' {
+			let mut start = i + 1;
+			if chars[i] == '{' { while start < chars.len() && chars[start].is_whitespace() { start += 1; } }
+			let mut end = start;
+			while end < chars.len() && is_name_char(chars[end]) { end += 1; }
+			let written: String = chars[start..end].iter().cloned().collect();
+			let name = dialect.strings.check(&written).map(|n| n.into_owned()).unwrap_or(written);
+			if chars[i] == '{' { names.push((name, columns[start], columns[end])); }
+			else { pointers.push((name, columns[i], columns[end])); }
+			i = end;
+		} else { i += 1; }
+	}
+	(names, pointers)
+}
+
+// report a problem covering the text of line 'n' (without its surrounding whitespace)
+fn line_problem (raw: &str, n: usize, severity: usize, message: &str) -> Problem {
+	let columns = utf16_columns(raw);
+	let chars = raw.chars().count();
+	let leading = raw.chars().take_while(|c| c.is_whitespace()).count();
+	let trailing = raw.chars().rev().take_while(|c| c.is_whitespace()).count();
+	let end = if leading == chars { chars } else { chars - trailing };
+	Problem { line: n, start: columns[leading], end: columns[end], severity, message: message.to_string() }
+}
+
+// the names of the lists an INCLUDE line brings in, read the same way the command line reads them
+fn included_names (dir: &Path, path: &str, own: Option<&PathBuf>, dialect: &Dialect, limits: &Limits) -> Result<Vec<String>, String> {
+	let mut state = InputState::new(Mode::Buffered);
+	state.dialect = *dialect;
+	state.limits = *limits;
+	let mut stack: Vec<PathBuf> = own.into_iter().cloned().collect();
+	match include_file(&mut state, dir, path, &mut stack) {
+		Ok(()) => Ok(list_names(&state.datatree).iter().map(|n| n.to_string()).collect()),
+		Err(diagnostic) => Err(diagnostic.to_string())
+	}
+}
+
+// what a line means on its own, before we know which section it falls in
+enum LineKind {
+	Separator,
+	Quit,
+	// the names the included file defines, or why it could not be read
+	Include(Result<Vec<String>, String>),
+	// whether the line is a valid LIST, and if so the names and pointers in it
+	Data(bool, Spans, Spans)
+}
+
+// the cached parse result for one line
+struct Line {
+	text: String,
+	kind: LineKind,
+	// whether the line would be a valid ONEQ
+	query: bool,
+	// the section the line falls in, as of the last edit
+	section: Section,
+	// whether the line's names and pointers are in the document's counts right now
+	counted: bool
+}
+
+impl Line {
+	// the line as the command line would read it with the same DIALECT and LIMITS flags
+	fn parse (text: &str, dir: &Path, path: Option<&PathBuf>, dialect: &Dialect, limits: &Limits) -> Line {
+		let trimmed = text.trim();
+		let kind = if trimmed == "." { LineKind::Separator }
+			else if trimmed == "QUIT" { LineKind::Quit }
+			else {
+				match check_include(trimmed) {
+					Some(Ok(included)) => LineKind::Include(included_names(dir, &included, path, dialect, limits)),
+					Some(Err(message)) => LineKind::Include(Err(message.to_string())),
+					None => {
+						if parse_list(trimmed, dialect, limits.depth).is_ok() {
+							let (names, pointers) = scan_line(text, dialect);
+							LineKind::Data(true, names, pointers)
+						} else { LineKind::Data(false, vec![], vec![]) }
+					}
+				}
+			};
+		Line { text: text.to_string(), kind, query: parse_query(trimmed, dialect).0, section: Section::Data, counted: false }
+	}
+
+	// whether a data section would accept this line as defining something (an empty line, such
+	// as the piece after a final newline, never does)
+	fn countable (&self) -> bool {
+		matches!(self.kind, LineKind::Include(Ok(_)) | LineKind::Data(true, _, _))
+	}
+
+	// the section the line after this one falls in
+	fn next_section (&self) -> Section {
+		match (self.section, &self.kind) {
+			(Section::Data, &LineKind::Separator) => Section::Queries,
+			(Section::Queries, &LineKind::Quit) => Section::Done,
+			(section, _) => section
+		}
+	}
+}
+
+// add one to (or take one from) the count of 'name'; true if that took it to or from 0 or 1,
+// which is when a list name starts or stops being defined, or being defined more than once
+fn bump (counts: &mut HashMap<String, usize>, name: &str, up: bool) -> bool {
+	let before = counts.get(name).cloned().unwrap_or(0);
+	let after = if up { before + 1 } else { before - 1 };
+	if after == 0 { counts.remove(name); } else { counts.insert(name.to_string(), after); }
+	before.min(2) != after.min(2)
+}
+
+// split text into lines the way an editor numbers them: a trailing newline leaves an empty last line
+fn split_lines (text: &str) -> Vec<&str> {
+	text.split('\n').map(|l| l.strip_suffix('\r').unwrap_or(l)).collect()
+}
+
+pub struct Document {
+	path: Option<PathBuf>,
+	dir: PathBuf,
+	// the language the lines are written in, as the lsp command line gives it
+	dialect: Dialect,
+	limits: Limits,
+	lines: Vec<Line>,
+	// how many times each list name is defined (in the data section, includes counted too)
+	names: HashMap<String, usize>,
+	// how many times each pointer target is referenced in the data section
+	pointers: HashMap<String, usize>,
+	// names that went to or from being defined, or defined more than once, during this edit
+	flipped: HashSet<String>,
+	analysis: Analysis,
+	// whether the last problem is the one saying the input ended before QUIT
+	unfinished: bool,
+	// how many lines have been parsed since the document was opened
+	parsed: usize
+}
+
+impl Document {
+	pub fn new (text: &str, path: Option<PathBuf>, dialect: Dialect, limits: Limits) -> Document {
+		let dir = path.as_ref().and_then(|p| p.parent()).unwrap_or_else(|| Path::new("")).to_path_buf();
+		let analysis = Analysis { problems: vec![], names: vec![], pointers: vec![], included: vec![] };
+		let mut document = Document {
+			path, dir, dialect, limits, lines: vec![], names: HashMap::new(), pointers: HashMap::new(),
+			flipped: HashSet::new(), analysis, unfinished: false, parsed: 0
+		};
+		document.set_text(text);
+		document
+	}
+
+	// the whole text, as the property test compares it with the edits it made
+	#[cfg(test)]
+	fn text (&self) -> String {
+		self.lines.iter().map(|l| &*l.text).collect::<Vec<&str>>().join("\n")
+	}
+
+	// the text of line 'n', if there is one
+	pub fn line (&self, n: usize) -> Option<&str> {
+		self.lines.get(n).map(|l| &*l.text)
+	}
+
+	// replace the whole text, reparsing only the lines between the unchanged start and end
+	pub fn set_text (&mut self, text: &str) {
+		let new = split_lines(text);
+		let mut prefix = 0;
+		while prefix < new.len() && prefix < self.lines.len() && self.lines[prefix].text == new[prefix] { prefix += 1; }
+		let mut suffix = 0;
+		while suffix < new.len() - prefix && suffix < self.lines.len() - prefix
+			&& self.lines[self.lines.len()-1-suffix].text == new[new.len()-1-suffix] { suffix += 1; }
+		let end = self.lines.len() - suffix;
+		self.splice(prefix, end, &new[prefix..new.len()-suffix]);
+	}
+
+	// replace the text between two (line, UTF-16 column) positions, as an LSP range change does
+	pub fn apply_change (&mut self, start: (usize, usize), end: (usize, usize), inserted: &str) {
+		let last = self.lines.len() - 1;
+		let (first, last) = (start.0.min(last), end.0.min(last));
+		let joined = {
+			let head = &self.lines[first].text;
+			let tail = &self.lines[last].text;
+			head[..byte_offset(head, start.1)].to_string() + inserted + &tail[byte_offset(tail, end.1)..]
+		};
+		self.splice(first, last + 1, &split_lines(&joined));
+	}
+
+	// put new lines in place of lines[start..end], then bring the counts and the analysis up to date
+	fn splice (&mut self, start: usize, end: usize, new: &[&str]) {
+		for n in start..end {
+			if self.lines[n].counted { self.count(n, false); }
+		}
+		let parsed: Vec<Line> = new.iter().map(|t| Line::parse(t, &self.dir, self.path.as_ref(), &self.dialect, &self.limits)).collect();
+		self.parsed += parsed.len();
+		self.lines.splice(start..end, parsed);
+		if self.unfinished { self.analysis.problems.pop(); }
+		let moved = new.len() as isize - (end - start) as isize;
+		remove_lines(&mut self.analysis.problems, start, end, moved);
+		remove_lines(&mut self.analysis.names, start, end, moved);
+		remove_lines(&mut self.analysis.pointers, start, end, moved);
+		remove_lines(&mut self.analysis.included, start, end, moved);
+
+		// the new lines take their sections, and so do the lines after them until one is found
+		// already in the section it falls in now (from there on nothing has changed)
+		let mut section = if start == 0 { Section::Data } else { self.lines[start-1].next_section() };
+		let mut stop = start;
+		while stop < self.lines.len() && (stop < start + new.len() || self.lines[stop].section != section) {
+			self.lines[stop].section = section;
+			let wanted = section == Section::Data && self.lines[stop].countable();
+			if wanted != self.lines[stop].counted { self.count(stop, wanted); }
+			section = self.lines[stop].next_section();
+			stop += 1;
+		}
+		self.report(start, stop);
+
+		// the last line is not reported if it is the empty piece after a final newline, so the
+		// last two lines may have changed whether they are reported
+		let len = self.lines.len();
+		for n in len.saturating_sub(2)..len {
+			if n < start || n >= stop { self.report(n, n + 1); }
+		}
+		// the names this edit made defined or undefined, single or duplicated, change the
+		// warnings on every line that names or points to them
+		if !self.flipped.is_empty() {
+			let flipped = std::mem::take(&mut self.flipped);
+			let mut redo: Vec<usize> = self.analysis.names.iter().chain(&self.analysis.pointers)
+				.filter(|s| flipped.contains(&s.name) && (s.line < start || s.line >= stop)).map(|s| s.line).collect();
+			redo.sort_unstable();
+			redo.dedup();
+			for n in redo { self.report(n, n + 1); }
+		}
+
+		let total = self.line_count();
+		self.unfinished = total > 0 && self.lines[total-1].next_section() == Section::Queries;
+		if self.unfinished {
+			self.analysis.problems.push(line_problem(&self.lines[total-1].text, total-1, 1, "input ended before QUIT"));
+		}
+	}
+
+	// add (or take away) the names and pointers of line 'n' from the counts
+	fn count (&mut self, n: usize, up: bool) {
+		let Document { ref mut lines, ref mut names, ref mut pointers, ref mut flipped, .. } = *self;
+		match lines[n].kind {
+			LineKind::Include(Ok(ref included)) => for name in included {
+				if bump(names, name, up) { flipped.insert(name.clone()); }
+			},
+			LineKind::Data(true, ref defined, ref referenced) => {
+				for symbol in defined {
+					if bump(names, &symbol.0, up) { flipped.insert(symbol.0.clone()); }
+				}
+				for symbol in referenced { bump(pointers, &symbol.0, up); }
+			},
+			_ => {}
+		}
+		lines[n].counted = up;
+	}
+
+	// the lines that are really lines: the empty piece after a final newline is not one
+	fn line_count (&self) -> usize {
+		let len = self.lines.len();
+		if len > 0 && self.lines[len-1].text.is_empty() { return len - 1; }
+		len
+	}
+
+	// work out again what lines from..to contribute to the analysis, from their cached parses,
+	// sections and the counts, and put it in place of what they contributed before
+	fn report (&mut self, from: usize, to: usize) {
+		let mut problems: Vec<Problem> = vec![];
+		let mut names: Vec<Symbol> = vec![];
+		let mut pointers: Vec<Symbol> = vec![];
+		let mut included: Vec<(usize, String)> = vec![];
+		for n in from..to.min(self.line_count()) {
+			let line = &self.lines[n];
+			let raw = &*line.text;
+			match line.section {
+				Section::Data => match line.kind {
+					LineKind::Separator => {},
+					LineKind::Include(Ok(ref defined)) => included.extend(defined.iter().map(|name| (n, name.clone()))),
+					LineKind::Include(Err(ref message)) => problems.push(line_problem(raw, n, 1, message)),
+					LineKind::Data(true, ref defined, ref referenced) => {
+						// NAMECHECK and PTRS, reported where the offending names are written
+						let mut warnings: Vec<Problem> = vec![];
+						for s in defined {
+							if self.names.get(&s.0).is_some_and(|&count| count > 1) {
+								let message = format!("duplicate list name '{}'", s.0);
+								warnings.push(Problem { line: n, start: s.1, end: s.2, severity: 2, message });
+							}
+							names.push(Symbol { name: s.0.clone(), line: n, start: s.1, end: s.2 });
+						}
+						for s in referenced {
+							if !self.names.contains_key(&s.0) {
+								let message = format!("dangling pointer: no list named '{}'", s.0);
+								warnings.push(Problem { line: n, start: s.1, end: s.2, severity: 2, message });
+							}
+							pointers.push(Symbol { name: s.0.clone(), line: n, start: s.1, end: s.2 });
+						}
+						warnings.sort_by_key(|w| w.start);
+						problems.extend(warnings);
+					},
+					_ => problems.push(line_problem(raw, n, 1, "malformed data line"))
+				},
+				Section::Queries => match line.kind {
+					LineKind::Quit => {},
+					_ => if !line.query { problems.push(line_problem(raw, n, 1, "malformed query")); }
+				},
+				Section::Done => problems.push(line_problem(raw, n, 1, "input continues after QUIT"))
+			}
+		}
+		replace_lines(&mut self.analysis.problems, from, to, problems);
+		replace_lines(&mut self.analysis.names, from, to, names);
+		replace_lines(&mut self.analysis.pointers, from, to, pointers);
+		replace_lines(&mut self.analysis.included, from, to, included);
+	}
+
+	// the problems, names and pointers of the whole document, as the last edit left them
+	pub fn analysis (&self) -> &Analysis {
+		&self.analysis
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::rng::Rng;
+
+	// PROPERTY: however a document is edited, the incrementally kept result must equal a full reparse
+	// Each round starts from a random document and applies random range edits and whole-text
+	// replacements, checking the text, the analysis and the counts against a fresh Document every step,
+	// and that the edit parsed only the lines it replaced
+	fn check_incremental (seed: u64, rounds: u64) -> Result<(), String> {
+		let pool = ["{a: 1, 2}", "{b: @a, {c: x}}", "{a: @zz}", "{c:@b}", "{bad", ".", "SUM", "search a", "QUIT",
+			"", "  {e: 1} ", "{d: 1, @c, {e: @d}}", "NAMECHECK", "ptrs", "{\u{e9}: 1}", "INCLUDE \"", "  .  "];
+		let fragments = ["\n", "{", "}", "@", "a", "b", ",", ".", "QUIT", ":", " ", "\u{e9}", "1", "{a:", "\n.\n"];
+		for round in 0..rounds {
+			let mut rng = Rng::new(seed.wrapping_add(round));
+			let mut lines: Vec<&str> = vec![];
+			for _ in 0..rng.below(12) { lines.push(pool[rng.below(pool.len())]); }
+			let mut text = lines.join("\n");
+			if rng.below(2) == 0 { text.push('\n'); }
+			let mut document = Document::new(&text, None, Dialect::new(), Limits::new());
+			for step in 0..30 {
+				let parsed = document.parsed;
+				let replaced;
+				if rng.below(3) == 0 {
+					// swap one whole line for another from the pool
+					let mut lines: Vec<String> = text.split('\n').map(|l| l.to_string()).collect();
+					let n = rng.below(lines.len());
+					let line = pool[rng.below(pool.len())].to_string();
+					replaced = if lines[n] == line { 0 } else { 1 };
+					lines[n] = line;
+					text = lines.join("\n");
+					document.set_text(&text);
+				} else {
+					// a range edit between two random positions, applied to the plain text separately
+					let lines: Vec<&str> = text.split('\n').collect();
+					let mut a = (rng.below(lines.len()), 0);
+					a.1 = rng.below(utf16_columns(lines[a.0]).last().unwrap() + 1);
+					let mut b = (rng.below(lines.len()), 0);
+					b.1 = rng.below(utf16_columns(lines[b.0]).last().unwrap() + 1);
+					if b < a { std::mem::swap(&mut a, &mut b); }
+					let mut inserted = String::new();
+					for _ in 0..rng.below(3) { inserted.push_str(fragments[rng.below(fragments.len())]); }
+					let offset = |pos: (usize, usize)| -> usize {
+						lines[..pos.0].iter().map(|l| l.len() + 1).sum::<usize>() + byte_offset(lines[pos.0], pos.1)
+					};
+					let (from, to) = (offset(a), offset(b));
+					text = text[..from].to_string() + &*inserted + &text[to..];
+					document.apply_change(a, b, &inserted);
+					// the lines from a to b become as many as the inserted text makes
+					replaced = inserted.matches('\n').count() + 1;
+				}
+				let fresh = Document::new(&text, None, Dialect::new(), Limits::new());
+				let context = format!("seed {} step {}: {:?}", seed.wrapping_add(round), step, text);
+				if document.text() != text { return Err(format!("{}: text is {:?}", context, document.text())); }
+				if document.parsed - parsed != replaced {
+					return Err(format!("{}: parsed {} lines for an edit of {}", context, document.parsed - parsed, replaced));
+				}
+				if document.analysis() != fresh.analysis() {
+					return Err(format!("{}: analysis differs\nincremental: {:?}\nfull: {:?}", context, document.analysis(), fresh.analysis()));
+				}
+				if document.names != fresh.names || document.pointers != fresh.pointers {
+					return Err(format!("{}: name/pointer counts differ", context));
+				}
+			}
+		}
+		Ok(())
+	}
+
+	#[test]
+	fn incremental_matches_full_reparse () {
+		check_incremental(1, 200).unwrap();
+	}
+
+	#[test]
+	fn documents_are_checked_in_their_dialect () {
+		let text = "{s: \"New York\", k=@t}\n{t: 1.5}\n.\nSEARCH \"New York\"\nGET s.k\nQUIT\n";
+		let plain = Document::new(text, None, Dialect::new(), Limits::new());
+		let lines: Vec<usize> = plain.analysis().problems.iter().map(|p| p.line).collect();
+		assert_eq!(lines, [0, 1, 3, 4]);
+		let mut dialect = Dialect::new();
+		dialect.quoted = true;
+		dialect.keys = true;
+		dialect.numbers.decimal = true;
+		let document = Document::new(text, None, dialect, Limits::new());
+		let analysis = document.analysis();
+		assert_eq!(analysis.problems, []);
+		let names: Vec<&str> = analysis.names.iter().map(|s| &*s.name).collect();
+		let pointers: Vec<(&str, usize, usize)> = analysis.pointers.iter().map(|s| (&*s.name, s.start, s.end)).collect();
+		assert_eq!((names, pointers), (vec!["s", "t"], vec![("t", 18, 20)]));
+	}
+}
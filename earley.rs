@@ -0,0 +1,358 @@
+// Earley parsing for any context-free grammar, left-recursive and ambiguous ones included
+// The recognizer builds the usual chart of (production, dot, origin) items, with nullable
+// nonterminals handled as Aycock and Horspool do (predicting one also steps over it).
+// From the completed items it builds a shared packed parse forest: one node per
+// (nonterminal, span), holding every way (production and split of the span) it was derived.
+// A node with more than one such family is where the input is ambiguous.
+use std::collections::{HashMap, HashSet};
+use super::grammar::{Grammar, Symbol, Token, Tree, unexpected};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Item {
+	production: usize,
+	dot: usize,
+	origin: usize
+}
+
+// a child in a family: a token (by index) or a forest node (nonterminal, start, end)
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Child {
+	Token(usize),
+	Node(String, usize, usize)
+}
+
+// a node's packed families: the ways of deriving it, as (production, children)
+pub type Families = Vec<(usize, Vec<Child>)>;
+
+// SPPF: for each (nonterminal, start, end) node, its packed families (production, children)
+pub struct Forest {
+	pub root: (String, usize, usize),
+	pub nodes: HashMap<(String, usize, usize), Families>,
+	// nodes in the order they were reached from the root, for stable output
+	pub order: Vec<(String, usize, usize)>
+}
+
+pub struct Earley<'a> {
+	grammar: &'a Grammar,
+	pub tokens: Vec<Token>,
+	sets: Vec<Vec<Item>>,
+	// (nonterminal, start) -> every end it was completed at, and the productions completed per span
+	ends: HashMap<(String, usize), Vec<usize>>,
+	completed: HashMap<(String, usize, usize), Vec<usize>>
+}
+
+impl<'a> Earley<'a> {
+	// RECOGNIZE: fill in the chart for 'input'
+	pub fn run (grammar: &'a Grammar, input: &str) -> Result<Earley<'a>, String> {
+		let tokens = grammar.tokenize(input)?;
+		let nullable = grammar.nullable();
+		let n = tokens.len();
+		let mut sets: Vec<Vec<Item>> = vec![vec![]; n+1];
+		let mut seen: Vec<HashSet<Item>> = vec![HashSet::new(); n+1];
+		fn add (sets: &mut [Vec<Item>], seen: &mut [HashSet<Item>], k: usize, item: Item) {
+			if seen[k].insert(item) { sets[k].push(item); }
+		}
+		for &p in grammar.alternatives(&grammar.start) { add(&mut sets, &mut seen, 0, Item { production: p, dot: 0, origin: 0 }); }
+		for k in 0..n+1 {
+			let mut i = 0;
+			while i < sets[k].len() {
+				let item = sets[k][i];
+				i += 1;
+				let production = &grammar.productions[item.production];
+				if item.dot < production.rhs.len() {
+					match production.rhs[item.dot] {
+						// PREDICT, stepping straight over a nullable nonterminal too
+						Symbol::Nonterminal(ref name) => {
+							for &q in grammar.alternatives(name) { add(&mut sets, &mut seen, k, Item { production: q, dot: 0, origin: k }); }
+							if nullable.contains(name) { add(&mut sets, &mut seen, k, Item { dot: item.dot + 1, ..item }); }
+						},
+						// SCAN
+						ref terminal => {
+							if k < n && tokens[k].kind == *terminal { add(&mut sets, &mut seen, k+1, Item { dot: item.dot + 1, ..item }); }
+						}
+					}
+				} else {
+					// COMPLETE: advance every item in the origin set that was waiting for this nonterminal
+					let mut j = 0;
+					while j < sets[item.origin].len() {
+						let waiting = sets[item.origin][j];
+						j += 1;
+						let rhs = &grammar.productions[waiting.production].rhs;
+						if waiting.dot < rhs.len() && rhs[waiting.dot] == Symbol::Nonterminal(production.lhs.clone()) {
+							add(&mut sets, &mut seen, k, Item { dot: waiting.dot + 1, ..waiting });
+						}
+					}
+				}
+			}
+		}
+
+		let mut ends: HashMap<(String, usize), Vec<usize>> = HashMap::new();
+		let mut completed: HashMap<(String, usize, usize), Vec<usize>> = HashMap::new();
+		for (k, set) in sets.iter().enumerate() {
+			for item in set {
+				let production = &grammar.productions[item.production];
+				if item.dot < production.rhs.len() { continue; }
+				let found = ends.entry((production.lhs.clone(), item.origin)).or_insert(vec![]);
+				if !found.contains(&k) { found.push(k); }
+				completed.entry((production.lhs.clone(), item.origin, k)).or_insert(vec![]).push(item.production);
+			}
+		}
+		Ok(Earley { grammar, tokens, sets, ends, completed })
+	}
+
+	pub fn accepted (&self) -> bool {
+		self.completed.contains_key(&(self.grammar.start.clone(), 0, self.tokens.len()))
+	}
+
+	// why the input was rejected: the first token after the last chart set that still had items
+	pub fn error (&self) -> String {
+		let mut last = 0;
+		for k in 0..self.sets.len() { if !self.sets[k].is_empty() { last = k; } }
+		unexpected(&self.tokens, last)
+	}
+
+	// the ways 'symbol' can cover tokens from pos, each as the child and where it ends (no further
+	// than 'end'), last first so they are popped in order
+	fn steps (&self, symbol: &Symbol, pos: usize, end: usize) -> Vec<(Child, usize)> {
+		let mut steps: Vec<(Child, usize)> = vec![];
+		match *symbol {
+			Symbol::Nonterminal(ref name) => {
+				for &e in self.ends.get(&(name.clone(), pos)).map(|e| &**e).unwrap_or(&[]) {
+					if e <= end { steps.push((Child::Node(name.clone(), pos, e), e)); }
+				}
+			},
+			ref terminal => {
+				if pos < end && self.tokens[pos].kind == *terminal { steps.push((Child::Token(pos), pos + 1)); }
+			}
+		}
+		steps.reverse();
+		steps
+	}
+
+	// every way 'rhs' can cover tokens start..end, as child lists
+	// The stack holds, for each symbol of 'rhs' reached so far, the steps not yet tried for it;
+	// 'current' holds the step taken for each symbol below the top of the stack.
+	fn splits (&self, rhs: &[Symbol], start: usize, end: usize) -> Vec<Vec<Child>> {
+		let mut out: Vec<Vec<Child>> = vec![];
+		if rhs.is_empty() {
+			if start == end { out.push(vec![]); }
+			return out;
+		}
+		let mut current: Vec<(Child, usize)> = vec![];
+		let mut stack: Vec<Vec<(Child, usize)>> = vec![self.steps(&rhs[0], start, end)];
+		while let Some(mut steps) = stack.pop() {
+			let step = match steps.pop() {
+				Some(step) => step,
+				// every step for this symbol has been tried: try the next one for the symbol before
+				None => { current.pop(); continue; }
+			};
+			let (k, pos) = (stack.len() + 1, step.1);
+			stack.push(steps);
+			current.push(step);
+			if k < rhs.len() {
+				stack.push(self.steps(&rhs[k], pos, end));
+			} else {
+				if pos == end { out.push(current.iter().map(|c| c.0.clone()).collect()); }
+				current.pop();
+			}
+		}
+		out
+	}
+
+	// FOREST: the packed families of every node reachable from the root (only for accepted input)
+	pub fn forest (&self) -> Forest {
+		let root = (self.grammar.start.clone(), 0, self.tokens.len());
+		let mut forest = Forest { root: root.clone(), nodes: HashMap::new(), order: vec![] };
+		let mut work = vec![root];
+		while let Some(key) = work.pop() {
+			if forest.nodes.contains_key(&key) { continue; }
+			let mut families: Families = vec![];
+			for &p in self.completed.get(&key).map(|p| &**p).unwrap_or(&[]) {
+				for children in self.splits(&self.grammar.productions[p].rhs, key.1, key.2) { families.push((p, children)); }
+			}
+			for family in &families {
+				for child in family.1.iter().rev() {
+					if let Child::Node(ref name, start, end) = *child { work.push((name.clone(), start, end)); }
+				}
+			}
+			forest.order.push(key.clone());
+			forest.nodes.insert(key, families);
+		}
+		forest
+	}
+}
+
+impl Forest {
+	// ENUMERATE: up to 'limit' distinct parse trees (a cyclic grammar can have infinitely many;
+	// a derivation that would revisit a node it is already inside is skipped)
+	// Each node being enumerated has a frame on the stack, and its children's trees are combined
+	// as they come back, so no depth of nesting can overflow the call stack.
+	pub fn trees (&self, tokens: &[Token], limit: usize) -> Vec<Tree> {
+		let mut active: HashSet<(String, usize, usize)> = HashSet::new();
+		active.insert(self.root.clone());
+		let mut stack: Vec<Frame> = vec![Frame::new(self, self.root.clone())];
+		loop {
+			let child = {
+				let frame = stack.last_mut().unwrap();
+				// once the node has its fill of trees, its other families are not needed
+				if frame.trees.len() >= limit { frame.family = frame.families.len(); }
+				match frame.families.get(frame.family) {
+					None => None,
+					Some(&(p, ref children)) => match children.get(frame.child) {
+						// a child with no trees leaves the family none, so the rest need not be enumerated
+						Some(child) if !frame.combos.is_empty() => Some(child.clone()),
+						// the family is done: each combination of its children's trees is a tree
+						_ => {
+							for combo in std::mem::replace(&mut frame.combos, vec![vec![]]) {
+								if frame.trees.len() >= limit { break; }
+								frame.trees.push(Tree::Node(p, combo));
+							}
+							frame.family += 1;
+							frame.child = 0;
+							continue;
+						}
+					}
+				}
+			};
+			let options = match child {
+				Some(Child::Token(t)) => vec![Tree::Leaf(tokens[t].clone())],
+				Some(Child::Node(name, start, end)) => {
+					let key = (name, start, end);
+					if !active.contains(&key) {
+						active.insert(key.clone());
+						stack.push(Frame::new(self, key));
+						continue;
+					}
+					vec![]
+				},
+				// every family is done: the node's trees are the options for its parent's child
+				None => {
+					let frame = stack.pop().unwrap();
+					active.remove(&frame.key);
+					if stack.is_empty() { return frame.trees; }
+					frame.trees
+				}
+			};
+			stack.last_mut().unwrap().combine(options, limit);
+		}
+	}
+
+	// AMBIGUITY: one line per node with more than one family, saying where and how
+	pub fn ambiguities (&self, grammar: &Grammar, tokens: &[Token], input: &str) -> Vec<String> {
+		let mut report: Vec<String> = vec![];
+		for key in &self.order {
+			let families = &self.nodes[key];
+			if families.len() < 2 { continue; }
+			let text = if key.1 < key.2 { &input[tokens[key.1].start..tokens[key.2-1].end] } else { "" };
+			report.push(format!("ambiguous at span {}..{} {:?}: {} has {} derivations", key.1, key.2, text, key.0, families.len()));
+			for &(p, ref children) in families {
+				report.push(format!("  {}  [{}]", grammar.productions[p], show_children(children)));
+			}
+		}
+		report
+	}
+
+	// the forest as text: each node, then each of its families
+	pub fn render (&self, grammar: &Grammar, tokens: &[Token]) -> String {
+		let mut out = String::new();
+		for key in &self.order {
+			out.push_str(&format!("({}, {}, {})\n", key.0, key.1, key.2));
+			for &(p, ref children) in &self.nodes[key] {
+				let shown: Vec<String> = children.iter().map(|c| match *c {
+					Child::Token(t) => format!("{}@{}", tokens[t].kind, t),
+					Child::Node(ref name, start, end) => format!("({}, {}, {})", name, start, end)
+				}).collect();
+				out.push_str(&format!("  {}  ->  {}\n", grammar.productions[p], shown.join(" ")));
+			}
+		}
+		out
+	}
+}
+
+// FRAME: one node whose trees are being enumerated, the family and child it is at, the
+// combinations of trees for the children before that one, and the node's trees so far
+struct Frame<'f> {
+	key: (String, usize, usize),
+	families: &'f [(usize, Vec<Child>)],
+	family: usize,
+	child: usize,
+	combos: Vec<Vec<Tree>>,
+	trees: Vec<Tree>
+}
+
+impl<'f> Frame<'f> {
+	fn new (forest: &'f Forest, key: (String, usize, usize)) -> Frame<'f> {
+		let families = forest.nodes.get(&key).map(|f| &**f).unwrap_or(&[]);
+		Frame { key, families, family: 0, child: 0, combos: vec![vec![]], trees: vec![] }
+	}
+
+	// every combination so far followed by each of the next child's trees, cut off at the limit;
+	// the last combination and the trees are moved rather than copied, so a node with one
+	// derivation takes its child's tree as it is
+	fn combine (&mut self, options: Vec<Tree>, limit: usize) {
+		let mut next: Vec<Vec<Tree>> = vec![];
+		let mut combos = std::mem::take(&mut self.combos);
+		let last = combos.pop();
+		for combo in &combos {
+			for option in &options {
+				if next.len() >= limit { break; }
+				let mut more = combo.clone();
+				more.push(option.clone());
+				next.push(more);
+			}
+		}
+		if let Some(mut combo) = last {
+			let n = options.len();
+			for (i, option) in options.into_iter().enumerate() {
+				if next.len() >= limit { break; }
+				let mut more = if i + 1 < n { combo.clone() } else { std::mem::take(&mut combo) };
+				more.push(option);
+				next.push(more);
+			}
+		}
+		self.combos = next;
+		self.child += 1;
+	}
+}
+
+// how a family splits its span: the token positions where each child starts and ends
+fn show_children (children: &[Child]) -> String {
+	let shown: Vec<String> = children.iter().map(|c| match *c {
+		Child::Token(t) => format!("{}", t),
+		Child::Node(_, start, end) => format!("{}..{}", start, end)
+	}).collect();
+	shown.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn ambiguous_left_recursive_grammars_give_a_forest () {
+		let grammar = Grammar::parse("E ::= E '+' E | N\nN = /[0-9]+/\n", "amb.grammar").unwrap();
+		let input = "1 + 2 + 3";
+		let earley = Earley::run(&grammar, input).unwrap();
+		assert!(earley.accepted());
+		let forest = earley.forest();
+		assert_eq!(forest.trees(&earley.tokens, 10).len(), 2);
+		let report = forest.ambiguities(&grammar, &earley.tokens, input);
+		assert_eq!(report[0], "ambiguous at span 0..5 \"1 + 2 + 3\": E has 2 derivations");
+		let rejected = Earley::run(&grammar, "1 + + 2").unwrap();
+		assert_eq!((rejected.accepted(), &*rejected.error()), (false, "unexpected \"+\" at offset 4"));
+	}
+
+	#[test]
+	fn deep_lines_give_their_tree_without_recursion () {
+		let depth = 2000;
+		let mut line = String::new();
+		for n in 0..depth { line.push_str(&format!("{{a{}: ", n)); }
+		line.push('1');
+		for _ in 0..depth { line.push('}'); }
+		let grammar = Grammar::data();
+		let earley = Earley::run(&grammar, &line).unwrap();
+		let trees = earley.forest().trees(&earley.tokens, 2);
+		assert_eq!(trees.len(), 1);
+		assert!(grammar.render_tree(&trees[0]).ends_with("'}' \"}\"\n"));
+	}
+}
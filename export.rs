@@ -0,0 +1,253 @@
+// EXPORT: an input written back out, either in one canonical form or as JSON
+// The formatter writes every data line as '{NAME: ITEM, ITEM}', with one space after each ':' and
+// ',' and none anywhere else, and every query in the form it is answered in. A STRING item is
+// written bare when the dialect reads it back as the same STRING, and as a QSTRING otherwise, so
+// formatting never changes what an input means. JSON export writes the data forest as an array
+// with one object per data line, with INCLUDE lines spliced in as the program reads them.
+// Neither recurses per nesting level: the lists still open are kept on an explicit stack.
+use std::io;
+use std::io::prelude::*;
+use super::{bignum, check_include, check_quoted, dialect_flag, feed_file, feed_stdin, json, parse_list, parse_query, Dialect, Diagnostic, InputState, Item, Limits, List, Mode, Node, Section, Type};
+
+// a STRING item as the formatter writes it: bare if that reads back as the same STRING, and
+// otherwise in quotes, with escapes for the quote, the backslash and the control chars
+fn push_string (out: &mut String, text: &str, dialect: &Dialect) {
+	if dialect.strings.check(text).as_deref() == Some(text) && !dialect.check_numeric(text) {
+		out.push_str(text);
+		return;
+	}
+	out.push('"');
+	for c in text.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\r' => out.push_str("\\r"),
+			'\t' => out.push_str("\\t"),
+			c if (c as u32) < 0x20 => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+			c => out.push(c)
+		}
+	}
+	out.push('"');
+}
+
+// a data line in canonical form
+fn format_list (list: &List, dialect: &Dialect) -> String {
+	let mut out = format!("{{{}:", list.name);
+	// the lists being written, each with how many of its items are written so far
+	let mut open: Vec<(&List, usize)> = vec![(list, 0)];
+	while let Some(top) = open.last_mut() {
+		let (list, written) = *top;
+		if written == list.items.len() {
+			out.push('}');
+			open.pop();
+			continue;
+		}
+		top.1 += 1;
+		out.push_str(if written == 0 { " " } else { ", " });
+		let item = match list.items[written] {
+			Item::Pair(ref key, ref value) => {
+				out.push_str(key);
+				out.push('=');
+				value
+			},
+			ref item => item
+		};
+		match *item {
+			Item::List(ref inner) => {
+				out.push('{');
+				out.push_str(&inner.name);
+				out.push(':');
+				open.push((inner, 0));
+			},
+			Item::Number(text) => out.push_str(text),
+			Item::Str(ref text) => push_string(&mut out, text, dialect),
+			Item::Pointer(ref name) => {
+				out.push('@');
+				out.push_str(name);
+			},
+			// the parser never gives a pair a pair
+			Item::Pair(..) => unreachable!()
+		}
+	}
+	out
+}
+
+// a query in canonical form, with a quoted SEARCH term written the way a STRING item would be
+fn format_query (query: &str, dialect: &Dialect) -> Option<String> {
+	let (valid, query) = parse_query(query, dialect);
+	if !valid { return None; }
+	if !query.starts_with("SEARCH \"") { return Some(query); }
+	let (_, text) = check_quoted(&query["SEARCH ".len()..])?;
+	let mut out = "SEARCH ".to_string();
+	push_string(&mut out, &text, dialect);
+	Some(out)
+}
+
+// every line of an input (or of a data file, which has no '.') in canonical form, or the number
+// and the problem of the first line that is not valid
+fn format_lines (text: &str, dialect: &Dialect, limits: &Limits) -> Result<Vec<String>, (usize, &'static str)> {
+	let mut lines: Vec<String> = vec![];
+	let mut section = Section::Data;
+	for (n, line) in text.lines().enumerate() {
+		let line = line.trim();
+		let formatted = match (section, line) {
+			(Section::Done, _) => Err("input continues after QUIT"),
+			(Section::Data, ".") => { section = Section::Queries; Ok(".".to_string()) },
+			(Section::Queries, "QUIT") => { section = Section::Done; Ok("QUIT".to_string()) },
+			(Section::Data, _) => match check_include(line) {
+				Some(included) => included.map(|path| format!("INCLUDE \"{}\"", path)),
+				None => parse_list(line, dialect, limits.depth).map(|list| format_list(&list, dialect))
+			},
+			(Section::Queries, _) => format_query(line, dialect).ok_or("malformed query")
+		};
+		lines.push(formatted.map_err(|message| (n + 1, message))?);
+	}
+	Ok(lines)
+}
+
+// a data tree as JSON: a list is {"name": NAME, "items": [...]}, a STRING a string, a NUMBER its
+// exact value in plain decimal, a pointer {"pointer": NAME} and a pair {"key": KEY, "value": ITEM}
+fn push_json (out: &mut String, node: &Node, dialect: &Dialect) {
+	// the lists and pairs being written, each with how many of its children are written so far
+	let mut open: Vec<(&Node, usize)> = vec![];
+	let mut next = Some(node);
+	loop {
+		if let Some(node) = next.take() {
+			match node.nodetype {
+				Type::List => {
+					out.push_str(&format!("{{\"name\": {}, \"items\": [", json::string(&node.value)));
+					open.push((node, 0));
+				},
+				Type::Pair => {
+					out.push_str(&format!("{{\"key\": {}, \"value\": ", json::string(&node.value)));
+					open.push((node, 0));
+				},
+				Type::ListString => out.push_str(&json::string(&node.value).to_string()),
+				Type::Number => match bignum::Decimal::parse(&node.value, &dialect.numbers) {
+					Some(number) => out.push_str(&number.to_string()),
+					None => out.push_str(&node.value)
+				},
+				Type::Pointer => out.push_str(&format!("{{\"pointer\": {}}}", json::string(&node.value)))
+			}
+		}
+		let top = match open.last_mut() { Some(top) => top, None => return };
+		let (node, written) = *top;
+		if written == node.children.len() {
+			out.push_str(if let Type::Pair = node.nodetype { "}" } else { "]}" });
+			open.pop();
+			continue;
+		}
+		top.1 += 1;
+		if written > 0 { out.push_str(", "); }
+		next = Some(&node.children[written]);
+	}
+}
+
+// the data forest as a JSON array, one data line to a line of output
+fn export_json (data: &[Node], dialect: &Dialect) -> String {
+	if data.is_empty() { return "[]".to_string(); }
+	let mut out = "[\n".to_string();
+	for (n, node) in data.iter().enumerate() {
+		push_json(&mut out, node, dialect);
+		out.push_str(if n + 1 < data.len() { ",\n" } else { "\n" });
+	}
+	out.push(']');
+	out
+}
+
+// wls254 format [DIALECT] [LIMITS] [FILE]: the input (stdin if no file is given) in canonical form
+pub fn format_command (args: &[String]) -> i32 {
+	let usage = || { eprintln!("usage: wls254 format [DIALECT] [LIMITS] [FILE]"); 2 };
+	let mut file: Option<String> = None;
+	let mut dialect = Dialect::new();
+	let mut limits = Limits::new();
+	let mut args = args.iter().cloned();
+	while let Some(arg) = args.next() {
+		if file.is_none() && !arg.starts_with("--") { file = Some(arg); continue; }
+		if !dialect_flag(&arg, &mut args, &mut dialect, &mut limits) { return usage(); }
+	}
+	let mut text = String::new();
+	let read = match file {
+		Some(ref path) => std::fs::File::open(path).and_then(|mut f| f.read_to_string(&mut text)),
+		None => io::stdin().read_to_string(&mut text)
+	};
+	let shown = file.unwrap_or_else(|| "<stdin>".to_string());
+	if let Err(e) = read { eprintln!("{}: {}", shown, e); return 2; }
+	match format_lines(&text, &dialect, &limits) {
+		Ok(lines) => {
+			for line in lines { println!("{}", line); }
+			0
+		},
+		Err((n, message)) => {
+			eprintln!("{}", Diagnostic::new(&shown, n, message));
+			1
+		}
+	}
+}
+
+// wls254 json [DIALECT] [LIMITS] [FILE]...: the data of the given data files, or of the whole
+// input on stdin (whose queries are checked but not answered), as JSON
+pub fn json_command (args: &[String]) -> i32 {
+	let mut files: Vec<String> = vec![];
+	let mut state = InputState::new(Mode::Buffered);
+	let mut args = args.iter().cloned();
+	while let Some(arg) = args.next() {
+		if !arg.starts_with("--") { files.push(arg); continue; }
+		if !dialect_flag(&arg, &mut args, &mut state.dialect, &mut state.limits) {
+			eprintln!("usage: wls254 json [DIALECT] [LIMITS] [FILE]...");
+			return 2;
+		}
+	}
+	let result = if files.is_empty() {
+		feed_stdin(&mut state).and_then(|_| state.ended().map_err(|message| Diagnostic::new("<stdin>", 0, message)))
+	} else {
+		files.iter().try_for_each(|path| feed_file(&mut state, path, Section::Data, &mut vec![]))
+	};
+	if let Err(diagnostic) = result { eprintln!("{}", diagnostic); return 1; }
+	println!("{}", export_json(&state.datatree, &state.dialect));
+	0
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn formatting_keeps_what_each_line_means () {
+		let mut dialect = Dialect::new();
+		dialect.quoted = true;
+		dialect.keys = true;
+		let text = "{a :1,\"New York\" ,\"b\",\"x\\u{41}\\ty\",k = @b, j={c:\"\\\"\\\\\", \"7\"}}\n  INCLUDE \"lib.txt\"\n.\nsearch  \"abc\"\nSEARCH \"a b\"\nget a.k\nQUIT";
+		let lines = format_lines(text, &dialect, &Limits::new()).unwrap();
+		assert_eq!(lines, ["{a: 1, \"New York\", b, \"xA\\ty\", k=@b, j={c: \"\\\"\\\\\", \"7\"}}", "INCLUDE \"lib.txt\"", ".", "SEARCH abc", "SEARCH \"a b\"", "GET a.k", "QUIT"]);
+		// the formatted input is its own canonical form
+		assert_eq!(format_lines(&lines.join("\n"), &dialect, &Limits::new()).unwrap(), lines);
+		assert_eq!(format_lines("{a: 1}\n{a: 1\n", &dialect, &Limits::new()), Err((2, super::super::MALFORMED)));
+		assert_eq!(format_lines("{a: 1}\n.\nQUIT\nSUM", &dialect, &Limits::new()), Err((4, "input continues after QUIT")));
+	}
+
+	#[test]
+	fn deep_lines_format_and_export_without_recursion () {
+		let depth = 20000;
+		let line = "{a: ".repeat(depth) + "1" + &"}".repeat(depth);
+		let dialect = Dialect::new();
+		let list = parse_list(&line, &dialect, depth).unwrap();
+		assert_eq!(format_list(&list, &dialect), line);
+		let exported = export_json(&[list.to_node()], &dialect);
+		assert!(exported.ends_with(&format!("1{}\n]", "]}".repeat(depth))));
+	}
+
+	#[test]
+	fn json_writes_exact_numbers_quoted_strings_and_pairs () {
+		let mut dialect = Dialect::new();
+		dialect.quoted = true;
+		dialect.keys = true;
+		dialect.numbers.decimal = true;
+		dialect.numbers.hex = true;
+		let list = parse_list("{a: 007, 0x10, 1.50, \"x \\\"y\\\"\", @b, k={c: -0}}", &dialect, 10).unwrap();
+		assert_eq!(export_json(&[list.to_node()], &dialect), concat!("[\n{\"name\": \"a\", \"items\": [7, 16, 1.5, \"x \\\"y\\\"\", {\"pointer\": \"b\"}, ",
+			"{\"key\": \"k\", \"value\": {\"name\": \"c\", \"items\": [0]}}]}\n]"));
+		assert_eq!(export_json(&[], &dialect), "[]");
+	}
+}
@@ -0,0 +1,419 @@
+// GENERATE: random valid inputs, for the data+query language or for any grammar
+// A data document is a few random lists, the '.' separator, a few queries and QUIT. The controls
+// bound how deep lists nest and how many items each holds, and set how often an item is a pointer
+// and how often a list takes a name that is already in use. The answers the program gives in the
+// chosen dialect and limits are worked out as the document is made, line by line as it reads
+// them, so a document and its answers together make a golden test. With --invalid the document gets one
+// labeled mistake instead, and the diagnostic the program must print for it is known up front.
+use std::fs::File;
+use std::io::prelude::*;
+use std::time::Instant;
+use super::{answer_query, dialect_flag, Dialect, Index, InputState, Limits, Mode};
+use super::grammar::Grammar;
+use super::rng::Rng;
+
+pub struct Controls {
+	// lists nest at most this deep (1 means no nested lists)
+	pub depth: usize,
+	// each list holds 1 to this many items
+	pub width: usize,
+	// the chance an item is a pointer
+	pub pointers: f64,
+	// the chance a list reuses a name instead of taking a new one
+	pub duplicates: f64,
+	pub lines: usize,
+	pub queries: usize
+}
+
+// what has been generated so far, for pointers and searches to refer back to
+struct Made {
+	names: Vec<String>,
+	values: Vec<String>,
+	next_name: usize,
+	next_dangling: usize
+}
+
+// separators come with or without spaces around them, as people write them
+fn spaced (rng: &mut Rng, text: &str) -> String {
+	let before = if rng.below(4) == 0 { " " } else { "" };
+	let after = if rng.below(4) == 0 { "" } else { " " };
+	format!("{}{}{}", before, text, after)
+}
+
+// LIST: a list being made, with the items made for it so far
+struct Partial {
+	name: String,
+	items: Vec<String>,
+	count: usize
+}
+
+impl Partial {
+	fn new (rng: &mut Rng, controls: &Controls, made: &mut Made) -> Partial {
+		let name = if !made.names.is_empty() && rng.chance(controls.duplicates) {
+			made.names[rng.below(made.names.len())].clone()
+		} else {
+			made.next_name += 1;
+			format!("l{}", made.next_name)
+		};
+		made.names.push(name.clone());
+		let count = 1 + rng.below(controls.width);
+		Partial { name, items: vec![], count }
+	}
+
+	fn text (&self, rng: &mut Rng) -> String {
+		let mut separated = String::new();
+		for (n, item) in self.items.iter().enumerate() {
+			if n > 0 { separated.push_str(&spaced(rng, ",")); }
+			separated.push_str(item);
+		}
+		format!("{{{}{}{}}}", self.name, spaced(rng, ":"), separated)
+	}
+}
+
+// a random data line; the lists it nests are made on a stack of their own, one level per entry,
+// so however deep the controls let them go, making them cannot overflow the call stack
+fn list (rng: &mut Rng, controls: &Controls, made: &mut Made) -> String {
+	let mut stack: Vec<Partial> = vec![Partial::new(rng, controls, made)];
+	loop {
+		let (depth, k, count) = {
+			let top = stack.last().unwrap();
+			(stack.len(), top.items.len(), top.count)
+		};
+		if k == count {
+			let text = stack.pop().unwrap().text(rng);
+			match stack.last_mut() {
+				Some(parent) => { parent.items.push(text); continue; },
+				None => return text
+			}
+		}
+		let item = if rng.chance(controls.pointers) {
+			// mostly to a list that exists, sometimes dangling
+			if rng.below(8) == 0 {
+				made.next_dangling += 1;
+				format!("@x{}", made.next_dangling)
+			} else { format!("@{}", made.names[rng.below(made.names.len())]) }
+		} else {
+			match rng.below(4) {
+				// a nested list may hold a list only as its last item (see Cursor::list)
+				0 if depth < controls.depth && (depth == 1 || k == count - 1) => {
+					let inner = Partial::new(rng, controls, made);
+					stack.push(inner);
+					continue;
+				},
+				0 | 1 => {
+					let number = format!("{}", rng.below(1100) as i64 - 100);
+					made.values.push(number.clone());
+					number
+				},
+				_ => {
+					let mut string = String::new();
+					string.push((b'a' + rng.below(6) as u8) as char);
+					if rng.below(2) == 0 { string.push((b'0' + rng.below(10) as u8) as char); }
+					made.values.push(string.clone());
+					string
+				}
+			}
+		};
+		stack.last_mut().unwrap().items.push(item);
+	}
+}
+
+fn query (rng: &mut Rng, made: &Made) -> String {
+	let lower = rng.below(2) == 0;
+	let word = |w: &str| if lower { w.to_lowercase() } else { w.to_string() };
+	match rng.below(5) {
+		0 => word("SUM"),
+		1 => word("PTRS"),
+		2 => word("NAMECHECK"),
+		_ => {
+			// usually something in the data, so searches find things
+			let look = match rng.below(4) {
+				0 if !made.names.is_empty() => made.names[rng.below(made.names.len())].clone(),
+				1 => format!("{}", rng.below(50)),
+				_ if !made.values.is_empty() => made.values[rng.below(made.values.len())].clone(),
+				_ => "a".to_string()
+			};
+			format!("{}{}{}", word("SEARCH"), if rng.below(4) == 0 { "\t" } else { " " }, look)
+		}
+	}
+}
+
+// a document, and the lines the program answers it with in 'dialect' within 'limits'
+// (a document the program would reject, such as one with more queries than the limits allow, is an error)
+pub fn generate (controls: &Controls, rng: &mut Rng, dialect: Dialect, limits: Limits) -> Result<(Vec<String>, Vec<String>), String> {
+	let mut made = Made { names: vec![], values: vec![], next_name: 0, next_dangling: 0 };
+	let mut document: Vec<String> = vec![];
+	let mut state = InputState::new(Mode::Buffered);
+	state.dialect = dialect;
+	state.limits = limits;
+	for _ in 0..controls.lines {
+		let line = list(rng, controls, &mut made);
+		if let Err(message) = state.feed(&line) { return Err(format!("generated a data line the program rejects ({}): {}", message, line)); }
+		document.push(line);
+	}
+	state.feed(".").unwrap();
+	document.push(".".to_string());
+	for _ in 0..controls.queries {
+		let line = query(rng, &made);
+		if let Err(message) = state.feed(&line) { return Err(format!("generated a query the program rejects ({}): {}", message, line)); }
+		document.push(line);
+	}
+	document.push("QUIT".to_string());
+	// answered as the program answers them once the input is complete, all within one --max-time
+	let deadline = state.deadline(Instant::now());
+	let index = Index::build(&state.datatree, &dialect);
+	let mut answers: Vec<String> = vec![];
+	for (query, line) in state.queries.iter().zip(&document[controls.lines+1..]) {
+		answers.push(answer_query(&state.datatree, &index, query, &dialect, deadline).map_err(|m| format!("cannot answer {}: {}", line, m))?);
+	}
+	Ok((document, answers))
+}
+
+// NEAR MISSES: one small mistake in a valid document, and the diagnostic the program must give for it
+pub const MUTATIONS: [&str; 7] = ["missing-colon", "unbalanced-brace", "digit-string", "at-number", "trailing-comma", "missing-dot", "missing-quit"];
+
+// the words of a data line (names, strings, numbers and pointers) as byte ranges, and whether each names a list
+fn words (line: &str) -> Vec<(usize, usize, bool)> {
+	let bytes = line.as_bytes();
+	let mut found: Vec<(usize, usize, bool)> = vec![];
+	let mut i = 0;
+	while i < bytes.len() {
+		let word = |b: u8| b.is_ascii_alphanumeric() || b == b'@' || b == b'-';
+		if !word(bytes[i]) { i += 1; continue; }
+		let start = i;
+		while i < bytes.len() && word(bytes[i]) { i += 1; }
+		let name = line[i..].trim().starts_with(':');
+		found.push((start, i, name));
+	}
+	found
+}
+
+// the byte offsets of every 'c' in 'line'
+fn offsets (line: &str, c: char) -> Vec<usize> {
+	line.char_indices().filter(|p| p.1 == c).map(|p| p.0).collect()
+}
+
+// apply mutation 'kind' to 'document' (as generate made it), giving the stdin diagnostic expected for the result
+pub fn mutate (document: &mut Vec<String>, kind: &str, rng: &mut Rng) -> Result<String, String> {
+	let dot = document.iter().position(|l| l == ".").unwrap();
+	match kind {
+		"missing-dot" => {
+			// the first query (or QUIT) is then read as a data line
+			document.remove(dot);
+			return Ok(format!("<stdin>:{}: malformed data line", dot + 1));
+		},
+		"missing-quit" => {
+			document.pop();
+			return Ok("<stdin>: input ended before QUIT".to_string());
+		},
+		_ => {}
+	}
+	if dot == 0 { return Err(format!("{} needs at least one data line", kind)); }
+	let n = rng.below(dot);
+	let mut line = document[n].clone();
+	match kind {
+		"missing-colon" => { let colons = offsets(&line, ':'); line.remove(colons[rng.below(colons.len())]); },
+		"unbalanced-brace" => {
+			// one '}' dropped or doubled
+			let closers = offsets(&line, '}');
+			let at = closers[rng.below(closers.len())];
+			if rng.below(2) == 0 { line.remove(at); } else { line.insert(at, '}'); }
+		},
+		"digit-string" => {
+			let names: Vec<(usize, usize, bool)> = words(&line).into_iter().filter(|w| line.as_bytes()[w.0].is_ascii_alphabetic()).collect();
+			let at = names[rng.below(names.len())].0;
+			line.insert(at, (b'0' + rng.below(10) as u8) as char);
+		},
+		"at-number" => {
+			let items: Vec<(usize, usize, bool)> = words(&line).into_iter().filter(|w| !w.2).collect();
+			let (start, end, _) = items[rng.below(items.len())];
+			line = format!("{}@{}{}", &line[..start], rng.below(100), &line[end..]);
+		},
+		"trailing-comma" => {
+			let closers = offsets(&line, '}');
+			let at = closers[rng.below(closers.len())];
+			line.insert_str(at, if rng.below(2) == 0 { "," } else { ", " });
+		},
+		_ => return Err(format!("unknown mutation {}; one of {}", kind, MUTATIONS.join(" ")))
+	}
+	document[n] = line;
+	Ok(format!("<stdin>:{}: malformed data line", n + 1))
+}
+
+// what the program says about a document read from stdin: None if it is accepted
+fn diagnose (document: &[String]) -> Option<String> {
+	let mut state = InputState::new(Mode::Buffered);
+	for (n, line) in document.iter().enumerate() {
+		if let Err(message) = state.feed(line) { return Some(format!("<stdin>:{}: {}", n + 1, message)); }
+	}
+	state.ended().err().map(|message| format!("<stdin>: {}", message))
+}
+
+// CHECK-NEGATIVE: every generated document must be accepted, and every near miss rejected with its diagnostic
+pub fn check_negative (seed: u64, rounds: u64) -> Result<(), String> {
+	for round in 0..rounds {
+		let mut rng = Rng::new(seed.wrapping_add(round));
+		let context = format!("seed {}", seed.wrapping_add(round));
+		let controls = Controls {
+			depth: 1 + rng.below(4), width: 1 + rng.below(5), pointers: rng.below(5) as f64 / 10.0,
+			duplicates: rng.below(3) as f64 / 10.0, lines: 1 + rng.below(5), queries: rng.below(6)
+		};
+		let (document, _) = generate(&controls, &mut rng, Dialect::new(), Limits::new()).map_err(|message| format!("{}: {}", context, message))?;
+		if let Some(diagnostic) = diagnose(&document) {
+			return Err(format!("{}: generated document rejected with {}\n{}", context, diagnostic, document.join("\n")));
+		}
+		for kind in MUTATIONS.iter() {
+			let mut mutant = document.clone();
+			let expected = mutate(&mut mutant, kind, &mut rng)?;
+			let got = diagnose(&mutant);
+			if got.as_ref() != Some(&expected) {
+				return Err(format!("{}: {}: expected {}, got {}\n{}", context, kind, expected, got.unwrap_or("no diagnostic".to_string()), mutant.join("\n")));
+			}
+		}
+	}
+	Ok(())
+}
+
+// wls254 check-negative [--seed N] [--rounds N]
+pub fn check_negative_command (args: &[String]) -> i32 {
+	let (mut seed, mut rounds) = (1, 200);
+	for pair in args.chunks(2) {
+		let value = pair.get(1).and_then(|v| v.parse::<u64>().ok());
+		match (&*pair[0], value) {
+			("--seed", Some(v)) => seed = v,
+			("--rounds", Some(v)) => rounds = v,
+			_ => { eprintln!("usage: wls254 check-negative [--seed N] [--rounds N]"); return 2; }
+		}
+	}
+	match check_negative(seed, rounds) {
+		Ok(()) => { println!("ok: {} near misses over {} rounds from seed {}", rounds as usize * MUTATIONS.len(), rounds, seed); 0},
+		Err(message) => { eprintln!("{}", message); 1}
+	}
+}
+
+// wls254 generate [DIALECT] [LIMITS] [--seed N] [--depth N] [--width N] [--pointers P] [--duplicates P] [--lines N] [--queries N] [--invalid KIND|any] [--expect FILE] [--diagnostic FILE]
+// wls254 generate --grammar FILE [--seed N] [--depth N] [--count N]
+pub fn generate_command (args: &[String]) -> i32 {
+	let usage = || {
+		eprintln!("usage: wls254 generate [DIALECT] [LIMITS] [--seed N] [--depth N] [--width N] [--pointers P] [--duplicates P] [--lines N] [--queries N] [--invalid KIND|any] [--expect FILE] [--diagnostic FILE]");
+		eprintln!("       wls254 generate --grammar FILE [--seed N] [--depth N] [--count N]");
+		2
+	};
+	let mut seed = 1;
+	let mut dialect = Dialect::new();
+	let mut limits = Limits::new();
+	let mut controls = Controls { depth: 3, width: 4, pointers: 0.2, duplicates: 0.1, lines: 4, queries: 5 };
+	let mut count = 10;
+	let (mut grammar_file, mut expect_file, mut diagnostic_file): (Option<String>, Option<String>, Option<String>) = (None, None, None);
+	let mut invalid: Option<String> = None;
+	// which options only make sense for the data language, or only with --grammar
+	let (mut data_only, mut grammar_only) = (false, false);
+	let mut args = args.iter().cloned();
+	while let Some(flag) = args.next() {
+		if dialect_flag(&flag, &mut args, &mut dialect, &mut limits) { data_only = true; continue; }
+		let value = match args.next() { Some(value) => value, None => return usage() };
+		let (count_value, probability) = (value.parse::<usize>().ok(), value.parse::<f64>().ok().filter(|p| *p >= 0.0 && *p <= 1.0));
+		match (&*flag, count_value, probability) {
+			("--seed", _, _) => match value.parse::<u64>() { Ok(v) => seed = v, Err(_) => return usage() },
+			("--depth", Some(v), _) if v > 0 => controls.depth = v,
+			("--width", Some(v), _) if v > 0 => { controls.width = v; data_only = true; },
+			("--lines", Some(v), _) => { controls.lines = v; data_only = true; },
+			("--queries", Some(v), _) => { controls.queries = v; data_only = true; },
+			("--pointers", _, Some(p)) => { controls.pointers = p; data_only = true; },
+			("--duplicates", _, Some(p)) => { controls.duplicates = p; data_only = true; },
+			("--expect", _, _) => { expect_file = Some(value); data_only = true; },
+			("--invalid", _, _) => { invalid = Some(value); data_only = true; },
+			("--diagnostic", _, _) => { diagnostic_file = Some(value); data_only = true; },
+			("--count", Some(v), _) => { count = v; grammar_only = true; },
+			("--grammar", _, _) => grammar_file = Some(value),
+			_ => return usage()
+		}
+	}
+	if (grammar_file.is_some() && data_only) || (grammar_file.is_none() && grammar_only) { return usage(); }
+	if diagnostic_file.is_some() && invalid.is_none() { return usage(); }
+
+	// sentences of a grammar: tokens joined by spaces, one sentence per line
+	if let Some(path) = grammar_file {
+		let grammar = match Grammar::load(&path) { Ok(g) => g, Err(message) => { eprintln!("{}", message); return 2; } };
+		let mut rng = Rng::new(seed);
+		for _ in 0..count {
+			match grammar.sample(&mut rng, controls.depth) {
+				Some(sentence) => println!("{}", sentence.join(" ")),
+				None => { eprintln!("the start symbol {} derives nothing", grammar.start); return 1; }
+			}
+		}
+		return 0;
+	}
+
+	let mut rng = Rng::new(seed);
+	let (mut document, mut answers) = match generate(&controls, &mut rng, dialect, limits) { Ok(pair) => pair, Err(message) => { eprintln!("{}", message); return 1; } };
+	// a near miss: the program prints only ERR, and the label and diagnostic go to stderr here
+	let mut diagnostic: Option<String> = None;
+	if let Some(kind) = invalid {
+		let kind = if kind == "any" { MUTATIONS[rng.below(MUTATIONS.len())].to_string() } else { kind };
+		match mutate(&mut document, &kind, &mut rng) {
+			Ok(expected) => { eprintln!("{}: {}", kind, expected); diagnostic = Some(expected); },
+			Err(message) => { eprintln!("{}", message); return 2; }
+		}
+		answers = vec!["ERR".to_string()];
+	}
+	for line in &document { println!("{}", line); }
+	for (path, lines) in &[(expect_file, answers), (diagnostic_file, diagnostic.into_iter().collect())] {
+		let path = match *path { Some(ref path) => path, None => continue };
+		let written = File::create(path).and_then(|mut file| {
+			for line in lines { writeln!(file, "{}", line)?; }
+			Ok(())
+		});
+		if let Err(e) = written { eprintln!("{}: {}", path, e); return 1; }
+	}
+	0
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn documents_are_answered_in_the_dialect_and_limits_given () {
+		let controls = Controls { depth: 3, width: 4, pointers: 0.2, duplicates: 0.1, lines: 4, queries: 5 };
+		let mut dialect = Dialect::new();
+		dialect.strings = super::super::Strings::Unicode;
+		dialect.numbers.decimal = true;
+		let (document, answers) = generate(&controls, &mut Rng::new(3), dialect, Limits::new()).unwrap();
+		assert_eq!((document.len(), answers.len()), (4 + 1 + 5 + 1, 5));
+		let mut limits = Limits::new();
+		limits.queries = 2;
+		let refused = generate(&controls, &mut Rng::new(3), dialect, limits).err().unwrap();
+		assert!(refused.starts_with("generated a query the program rejects (more queries than --max-queries allows)"), "{}", refused);
+	}
+
+	#[test]
+	fn sampled_sentences_belong_to_their_grammar () {
+		let grammar = Grammar::parse("E ::= T | T '+' E\nT ::= N | '(' E ')'\nN = /[0-9]+/\n", "expr.grammar").unwrap();
+		for seed in 0..50 {
+			let sentence = grammar.sample(&mut Rng::new(seed), 4).unwrap();
+			assert_eq!(grammar.sample(&mut Rng::new(seed), 4).as_ref(), Some(&sentence));
+			assert!(grammar.parse_input(&sentence.join(" ")).is_ok(), "{:?}", sentence);
+		}
+		let none = Grammar::parse("S ::= S 'x'\n", "loop.grammar").unwrap();
+		assert_eq!(none.sample(&mut Rng::new(1), 4), None);
+		// a generated document is always one the program accepts
+		let controls = Controls { depth: 4, width: 5, pointers: 0.3, duplicates: 0.2, lines: 6, queries: 6 };
+		for seed in 0..20 {
+			let (document, _) = generate(&controls, &mut Rng::new(seed), Dialect::new(), Limits::new()).unwrap();
+			assert_eq!(diagnose(&document), None, "{}", document.join("\n"));
+		}
+	}
+
+	#[test]
+	fn near_misses_get_their_diagnostics () {
+		check_negative(1, 50).unwrap();
+		let mut document: Vec<String> = ["{a: 1}", "{b: x, {c: 2}}", ".", "SUM", "QUIT"].iter().map(|l| l.to_string()).collect();
+		let mut missing = document.clone();
+		assert_eq!(mutate(&mut missing, "missing-dot", &mut Rng::new(1)), Ok("<stdin>:3: malformed data line".to_string()));
+		assert_eq!(missing[2], "SUM");
+		let expected = mutate(&mut document, "trailing-comma", &mut Rng::new(1)).unwrap();
+		assert_eq!(diagnose(&document), Some(expected));
+		assert!(mutate(&mut document, "typo", &mut Rng::new(1)).unwrap_err().starts_with("unknown mutation typo; one of missing-colon"));
+	}
+}
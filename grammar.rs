@@ -0,0 +1,967 @@
+// Generic context-free grammars: a grammar file format, a lexer built from its token classes, and a
+// backtracking parser that accepts any input for the grammar and builds a parse tree
+//
+// A grammar file has one definition per line ('#' starts a comment):
+//   LIST   ::= '{' NAME ':' ITEMS '}'        a nonterminal and its alternatives, split by '|'
+//          |   '{' '}'                       more alternatives on a line starting with '|'
+//   EMPTY  ::= %empty                        the empty alternative
+//   NUMBER =   /[-+]?[0-9]+/                 a token class: a name and a regex-like pattern
+//   %start LIST                              the start symbol (otherwise the first rule's)
+// Patterns support literal chars, escapes (\d \w \s \n \t), '.', [a-z] and [^a-z] sets, (groups),
+// '|', and the * + ? repeats. Whitespace between tokens is skipped; where a literal and a class
+// match the same longest text, the literal wins.
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+use super::cyk::{cyk, to_cnf};
+use super::earley::Earley;
+use super::lalr;
+use super::ll1;
+use super::rng::Rng;
+use super::trace::{Views, describe};
+
+// the data-line language of wls254.rs, bundled into the binary
+pub const DATA_GRAMMAR: &str = include_str!("data.grammar");
+
+// REGEX: a token class pattern
+#[derive(Clone, Debug)]
+enum Regex {
+	Char(char),
+	Any,
+	// (negated, ranges)
+	Set(bool, Vec<(char, char)>),
+	Concat(Vec<Regex>),
+	Alt(Vec<Regex>),
+	Star(Box<Regex>),
+	Plus(Box<Regex>),
+	Opt(Box<Regex>)
+}
+
+impl Regex {
+	fn parse (source: &str) -> Result<Regex, String> {
+		let chars: Vec<char> = source.chars().collect();
+		let mut pos = 0;
+		let regex = Regex::alternation(&chars, &mut pos)?;
+		if pos != chars.len() { return Err(format!("unexpected '{}' in pattern", chars[pos])); }
+		Ok(regex)
+	}
+
+	fn alternation (chars: &[char], pos: &mut usize) -> Result<Regex, String> {
+		let mut alternatives = vec![Regex::sequence(chars, pos)?];
+		while *pos < chars.len() && chars[*pos] == '|' {
+			*pos += 1;
+			alternatives.push(Regex::sequence(chars, pos)?);
+		}
+		if alternatives.len() == 1 { return Ok(alternatives.pop().unwrap()); }
+		Ok(Regex::Alt(alternatives))
+	}
+
+	fn sequence (chars: &[char], pos: &mut usize) -> Result<Regex, String> {
+		let mut items: Vec<Regex> = vec![];
+		while *pos < chars.len() && chars[*pos] != '|' && chars[*pos] != ')' {
+			let mut atom = Regex::atom(chars, pos)?;
+			while *pos < chars.len() {
+				atom = match chars[*pos] {
+					'*' => Regex::Star(Box::new(atom)),
+					'+' => Regex::Plus(Box::new(atom)),
+					'?' => Regex::Opt(Box::new(atom)),
+					_ => break
+				};
+				*pos += 1;
+			}
+			items.push(atom);
+		}
+		Ok(Regex::Concat(items))
+	}
+
+	// the set an escape like \d stands for, or None for an escaped literal char
+	fn escape_set (c: char) -> Option<Regex> {
+		match c {
+			'd' => Some(Regex::Set(false, vec![('0', '9')])),
+			'w' => Some(Regex::Set(false, vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')])),
+			's' => Some(Regex::Set(false, vec![(' ', ' '), ('\t', '\t'), ('\n', '\n'), ('\r', '\r')])),
+			_ => None
+		}
+	}
+
+	fn escape_char (c: char) -> char {
+		match c { 'n' => '\n', 't' => '\t', 'r' => '\r', c => c }
+	}
+
+	fn atom (chars: &[char], pos: &mut usize) -> Result<Regex, String> {
+		let c = chars[*pos];
+		*pos += 1;
+		match c {
+			'(' => {
+				let inner = Regex::alternation(chars, pos)?;
+				if *pos >= chars.len() || chars[*pos] != ')' { return Err("unclosed '(' in pattern".to_string()); }
+				*pos += 1;
+				Ok(inner)
+			},
+			'.' => Ok(Regex::Any),
+			'*' | '+' | '?' => Err(format!("nothing to repeat before '{}'", c)),
+			'\\' => {
+				if *pos >= chars.len() { return Err("pattern ends with '\\'".to_string()); }
+				*pos += 1;
+				Ok(Regex::escape_set(chars[*pos-1]).unwrap_or(Regex::Char(Regex::escape_char(chars[*pos-1]))))
+			},
+			'[' => {
+				let negated = *pos < chars.len() && chars[*pos] == '^';
+				if negated { *pos += 1; }
+				let mut ranges: Vec<(char, char)> = vec![];
+				loop {
+					if *pos >= chars.len() { return Err("unclosed '[' in pattern".to_string()); }
+					let mut low = chars[*pos];
+					*pos += 1;
+					if low == ']' && !ranges.is_empty() { break; }
+					if low == '\\' && *pos < chars.len() {
+						*pos += 1;
+						match Regex::escape_set(chars[*pos-1]) {
+							Some(Regex::Set(_, more)) => { ranges.extend(more); continue; },
+							_ => low = Regex::escape_char(chars[*pos-1])
+						}
+					}
+					let mut high = low;
+					if *pos + 1 < chars.len() && chars[*pos] == '-' && chars[*pos+1] != ']' {
+						high = chars[*pos+1];
+						*pos += 2;
+						if high == '\\' && *pos < chars.len() { high = Regex::escape_char(chars[*pos]); *pos += 1; }
+						if high < low { return Err(format!("bad range {}-{} in pattern", low, high)); }
+					}
+					ranges.push((low, high));
+				}
+				Ok(Regex::Set(negated, ranges))
+			},
+			c => Ok(Regex::Char(c))
+		}
+	}
+
+	// every position the pattern can end at when it starts matching at 'pos', in increasing order
+	fn ends (&self, chars: &[char], pos: usize) -> Vec<usize> {
+		match *self {
+			Regex::Char(c) => if pos < chars.len() && chars[pos] == c { vec![pos+1] } else { vec![] },
+			Regex::Any => if pos < chars.len() && chars[pos] != '\n' { vec![pos+1] } else { vec![] },
+			Regex::Set(negated, ref ranges) => {
+				if pos >= chars.len() { return vec![]; }
+				let inside = ranges.iter().any(|&(low, high)| low <= chars[pos] && chars[pos] <= high);
+				if inside != negated { vec![pos+1] } else { vec![] }
+			},
+			Regex::Concat(ref items) => {
+				let mut at = vec![pos];
+				for item in items {
+					let mut next: Vec<usize> = at.iter().flat_map(|&p| item.ends(chars, p)).collect();
+					next.sort();
+					next.dedup();
+					at = next;
+				}
+				at
+			},
+			Regex::Alt(ref alternatives) => {
+				let mut all: Vec<usize> = alternatives.iter().flat_map(|a| a.ends(chars, pos)).collect();
+				all.sort();
+				all.dedup();
+				all
+			},
+			Regex::Opt(ref inner) => {
+				let mut all = inner.ends(chars, pos);
+				all.push(pos);
+				all.sort();
+				all.dedup();
+				all
+			},
+			Regex::Star(ref inner) | Regex::Plus(ref inner) => {
+				// keep applying the inner pattern until no new end positions turn up
+				let mut seen: Vec<usize> = vec![];
+				let mut frontier = match *self { Regex::Star(_) => vec![pos], _ => inner.ends(chars, pos) };
+				while let Some(p) = frontier.pop() {
+					if seen.contains(&p) { continue; }
+					seen.push(p);
+					frontier.extend(inner.ends(chars, p).into_iter().filter(|&e| e > p));
+				}
+				seen.sort();
+				seen
+			}
+		}
+	}
+
+	// a random string the pattern matches (repeats are kept short, and sampled chars printable)
+	fn sample (&self, rng: &mut Rng) -> String {
+		match *self {
+			Regex::Char(c) => c.to_string(),
+			Regex::Any => ((b'!' + rng.below(94) as u8) as char).to_string(),
+			Regex::Set(false, ref ranges) => {
+				let (low, high) = ranges[rng.below(ranges.len())];
+				let code = low as u32 + rng.below((high as u32 - low as u32 + 1) as usize) as u32;
+				std::char::from_u32(code).unwrap_or(low).to_string()
+			},
+			Regex::Set(true, ref ranges) => {
+				let outside = |c: char| !ranges.iter().any(|&(low, high)| low <= c && c <= high);
+				let printable: Vec<char> = (b'!'..b'~' + 1).map(|b| b as char).filter(|&c| outside(c)).collect();
+				if printable.is_empty() { return "\u{100}".to_string(); }
+				printable[rng.below(printable.len())].to_string()
+			},
+			Regex::Concat(ref items) => items.iter().map(|i| i.sample(rng)).collect(),
+			Regex::Alt(ref alternatives) => alternatives[rng.below(alternatives.len())].sample(rng),
+			Regex::Star(ref inner) => (0..rng.below(3)).map(|_| inner.sample(rng)).collect(),
+			Regex::Plus(ref inner) => (0..1 + rng.below(3)).map(|_| inner.sample(rng)).collect(),
+			Regex::Opt(ref inner) => if rng.below(2) == 0 { inner.sample(rng) } else { String::new() }
+		}
+	}
+}
+
+// SYMBOL: a nonterminal, a literal terminal like '{', or a token class like NUMBER
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub enum Symbol {
+	Nonterminal(String),
+	Literal(String),
+	Class(String)
+}
+
+impl fmt::Display for Symbol {
+	fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Symbol::Nonterminal(ref name) | Symbol::Class(ref name) => write!(f, "{}", name),
+			Symbol::Literal(ref text) => write!(f, "'{}'", text.replace("\\", "\\\\").replace("'", "\\'"))
+		}
+	}
+}
+
+// one alternative of a nonterminal; an empty rhs is the empty alternative
+#[derive(Clone, PartialEq, Debug)]
+pub struct Production {
+	pub lhs: String,
+	pub rhs: Vec<Symbol>
+}
+
+impl fmt::Display for Production {
+	fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} ::=", self.lhs)?;
+		if self.rhs.is_empty() { return write!(f, " %empty"); }
+		for symbol in &self.rhs { write!(f, " {}", symbol)?; }
+		Ok(())
+	}
+}
+
+// a named token class, its pattern, and the pattern as written (for writing the grammar back out)
+#[derive(Clone, Debug)]
+pub struct TokenClass {
+	pub name: String,
+	pub source: String,
+	pattern: Regex
+}
+
+impl TokenClass {
+	// a random text of this class
+	pub fn sample (&self, rng: &mut Rng) -> String {
+		self.pattern.sample(rng)
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct Grammar {
+	pub start: String,
+	// nonterminals in the order they are first defined
+	pub nonterminals: Vec<String>,
+	pub productions: Vec<Production>,
+	pub classes: Vec<TokenClass>,
+	// the production indices of each nonterminal's alternatives
+	alternatives: HashMap<String, Vec<usize>>
+}
+
+// GRAMMAR FILE: written back out in the format Grammar::parse reads, one line per nonterminal
+impl fmt::Display for Grammar {
+	fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.nonterminals.first() != Some(&self.start) { writeln!(f, "%start {}", self.start)?; }
+		for nonterminal in &self.nonterminals {
+			let alternatives: Vec<String> = self.alternatives(nonterminal).iter().map(|&p| {
+				let rhs = &self.productions[p].rhs;
+				if rhs.is_empty() { return "%empty".to_string(); }
+				rhs.iter().map(|s| format!("{}", s)).collect::<Vec<String>>().join(" ")
+			}).collect();
+			writeln!(f, "{} ::= {}", nonterminal, alternatives.join(" | "))?;
+		}
+		for class in &self.classes { writeln!(f, "{} = /{}/", class.name, class.source.replace("/", "\\/"))?; }
+		Ok(())
+	}
+}
+
+// the pieces a grammar file line is made of
+#[derive(PartialEq, Debug)]
+enum Piece {
+	Name(String),
+	Define,
+	Equals,
+	Bar,
+	Quoted(String),
+	Pattern(String),
+	Directive(String)
+}
+
+fn is_name_start (c: char) -> bool { c.is_ascii_alphabetic() || c == '_' }
+fn is_name_char (c: char) -> bool { c.is_ascii_alphanumeric() || c == '_' }
+
+// split one line of a grammar file into pieces, dropping any comment
+fn pieces (line: &str) -> Result<Vec<Piece>, String> {
+	let chars: Vec<char> = line.chars().collect();
+	let mut found: Vec<Piece> = vec![];
+	let mut i = 0;
+	while i < chars.len() {
+		let c = chars[i];
+		if c.is_whitespace() { i += 1; continue; }
+		if c == '#' { break; }
+		if chars[i..].starts_with(&[':', ':', '=']) { found.push(Piece::Define); i += 3; continue; }
+		match c {
+			'=' => { found.push(Piece::Equals); i += 1; },
+			'|' => { found.push(Piece::Bar); i += 1; },
+			'\'' | '/' => {
+				// a quoted literal or a /pattern/; a backslash escapes the closing char
+				let mut text = String::new();
+				i += 1;
+				loop {
+					if i >= chars.len() { return Err(format!("unclosed {}", c)); }
+					if chars[i] == c { i += 1; break; }
+					if chars[i] == '\\' && i + 1 < chars.len() {
+						if c == '\'' && (chars[i+1] == '\'' || chars[i+1] == '\\') { text.push(chars[i+1]); i += 2; continue; }
+						if c == '/' && chars[i+1] == '/' { text.push('/'); i += 2; continue; }
+						if c == '/' { text.push('\\'); text.push(chars[i+1]); i += 2; continue; }
+					}
+					text.push(chars[i]);
+					i += 1;
+				}
+				found.push(if c == '\'' { Piece::Quoted(text) } else { Piece::Pattern(text) });
+			},
+			'%' => {
+				let start = i + 1;
+				i += 1;
+				while i < chars.len() && is_name_char(chars[i]) { i += 1; }
+				found.push(Piece::Directive(chars[start..i].iter().cloned().collect()));
+			},
+			c if is_name_start(c) => {
+				let start = i;
+				while i < chars.len() && is_name_char(chars[i]) { i += 1; }
+				found.push(Piece::Name(chars[start..i].iter().cloned().collect()));
+			},
+			c => return Err(format!("unexpected '{}'", c))
+		}
+	}
+	Ok(found)
+}
+
+// a token of the input being parsed: its terminal symbol, its text, and its byte span
+#[derive(Clone, PartialEq, Debug)]
+pub struct Token {
+	pub kind: Symbol,
+	pub text: String,
+	pub start: usize,
+	pub end: usize
+}
+
+// PARSE TREE: leaves are tokens, inner nodes record which production matched
+// Trees are copied and dropped with explicit stacks, so no depth of tree can overflow the call stack
+#[derive(PartialEq, Debug)]
+pub enum Tree {
+	Leaf(Token),
+	Node(usize, Vec<Tree>)
+}
+
+impl Clone for Tree {
+	fn clone (&self) -> Tree {
+		// the trees still to copy (and whether their children have been copied), and the copies made,
+		// each node's children on top of the stack in order by the time the node itself is made
+		let mut rest: Vec<(&Tree, bool)> = vec![(self, false)];
+		let mut made: Vec<Tree> = vec![];
+		while let Some((tree, ready)) = rest.pop() {
+			match *tree {
+				Tree::Leaf(ref token) => made.push(Tree::Leaf(token.clone())),
+				Tree::Node(p, ref children) if ready => {
+					let copies = made.split_off(made.len() - children.len());
+					made.push(Tree::Node(p, copies));
+				},
+				Tree::Node(_, ref children) => {
+					rest.push((tree, true));
+					rest.extend(children.iter().rev().map(|child| (child, false)));
+				}
+			}
+		}
+		made.pop().unwrap()
+	}
+}
+
+impl Drop for Tree {
+	fn drop (&mut self) {
+		let mut rest: Vec<Tree> = match *self { Tree::Node(_, ref mut children) => std::mem::take(children), _ => return };
+		while let Some(mut tree) = rest.pop() {
+			if let Tree::Node(_, ref mut children) = tree { rest.append(children); }
+		}
+	}
+}
+
+impl Grammar {
+	// read a grammar from text; 'file' is only used to name the source in error messages
+	pub fn parse (text: &str, file: &str) -> Result<Grammar, String> {
+		// first pass: the raw rules, with names not yet sorted into nonterminals and classes
+		let mut start: Option<String> = None;
+		let mut rules: Vec<(String, Vec<Piece>, usize)> = vec![];
+		let mut classes: Vec<TokenClass> = vec![];
+		for (n, line) in text.lines().enumerate() {
+			let error = |message: String| format!("{}:{}: {}", file, n+1, message);
+			let mut found = pieces(line).map_err(&error)?;
+			if found.is_empty() { continue; }
+			match found[0] {
+				Piece::Directive(ref d) if d == "start" => {
+					match (found.len(), found.get(1)) {
+						(2, Some(Piece::Name(name))) => start = Some(name.clone()),
+						_ => return Err(error("expected %start NAME".to_string()))
+					}
+					continue;
+				},
+				Piece::Bar => {
+					if rules.is_empty() { return Err(error("'|' with no rule to continue".to_string())); }
+					let last = rules.len() - 1;
+					rules[last].1.append(&mut found);
+					continue;
+				},
+				_ => {}
+			}
+			if found.len() < 2 { return Err(error("expected NAME ::= ... or NAME = /pattern/".to_string())); }
+			let name = match found[0] { Piece::Name(ref name) => name.clone(), _ => return Err(error("expected a name".to_string())) };
+			if found[1] == Piece::Equals {
+				let source = match (found.len(), found.get(2)) {
+					(3, Some(Piece::Pattern(source))) => source.clone(),
+					_ => return Err(error("expected NAME = /pattern/".to_string()))
+				};
+				let pattern = Regex::parse(&source).map_err(&error)?;
+				if classes.iter().any(|c| c.name == name) { return Err(error(format!("token class {} defined twice", name))); }
+				classes.push(TokenClass { name, source, pattern });
+			} else if found[1] == Piece::Define {
+				if rules.iter().any(|r| r.0 == name) { return Err(error(format!("{} defined twice (continue a rule with '|')", name))); }
+				rules.push((name, found.into_iter().skip(2).collect(), n+1));
+			} else { return Err(error("expected ::= or =".to_string())); }
+		}
+
+		// second pass: resolve names and split the alternatives
+		let mut grammar = Grammar { start: String::new(), nonterminals: vec![], productions: vec![], classes, alternatives: HashMap::new() };
+		for rule in &rules {
+			if grammar.classes.iter().any(|c| c.name == rule.0) {
+				return Err(format!("{}:{}: {} is both a rule and a token class", file, rule.2, rule.0));
+			}
+			grammar.nonterminals.push(rule.0.clone());
+		}
+		for (lhs, body, line) in rules {
+			let error = |message: String| format!("{}:{}: {}", file, line, message);
+			let mut alternatives: Vec<Vec<Symbol>> = vec![vec![]];
+			let mut empty = vec![false];
+			for piece in body {
+				let last = alternatives.len() - 1;
+				match piece {
+					Piece::Bar => { alternatives.push(vec![]); empty.push(false); },
+					Piece::Quoted(text) => {
+						if text.is_empty() { return Err(error("empty literal (write %empty)".to_string())); }
+						alternatives[last].push(Symbol::Literal(text));
+					},
+					Piece::Name(name) => {
+						if grammar.classes.iter().any(|c| c.name == name) { alternatives[last].push(Symbol::Class(name)); }
+						else if grammar.nonterminals.contains(&name) { alternatives[last].push(Symbol::Nonterminal(name)); }
+						else { return Err(error(format!("undefined symbol {}", name))); }
+					},
+					Piece::Directive(ref d) if d == "empty" => empty[last] = true,
+					piece => return Err(error(format!("unexpected {:?} in a rule", piece)))
+				}
+			}
+			for (rhs, is_empty) in alternatives.into_iter().zip(empty) {
+				if is_empty != (rhs.is_empty()) {
+					return Err(error("an alternative must be either %empty alone or non-empty".to_string()));
+				}
+				grammar.productions.push(Production { lhs: lhs.clone(), rhs });
+			}
+		}
+		if grammar.nonterminals.is_empty() { return Err(format!("{}: no rules", file)); }
+		grammar.start = match start {
+			Some(name) => {
+				if !grammar.nonterminals.contains(&name) { return Err(format!("{}: start symbol {} has no rule", file, name)); }
+				name
+			},
+			None => grammar.nonterminals[0].clone()
+		};
+		grammar.index();
+		Ok(grammar)
+	}
+
+	pub fn load (path: &str) -> Result<Grammar, String> {
+		match std::fs::read_to_string(path) {
+			Ok(text) => Grammar::parse(&text, path),
+			Err(e) => Err(format!("{}: cannot read: {}", path, e))
+		}
+	}
+
+	// the bundled data-line grammar
+	pub fn data () -> Grammar {
+		Grammar::parse(DATA_GRAMMAR, "data.grammar").expect("bundled grammar is valid")
+	}
+
+	// the bundled grammar for a dialect: other NUMBER and STRING patterns, for --numbers and
+	// --strings, with --quoted a QSTRING item (whose class can't say which escapes are allowed),
+	// and with --keys a PAIR item, KEY '=' ONEITEM (LAST takes a key in front of its items too)
+	pub fn data_dialect (number: &str, string: &str, quoted: bool, keys: bool) -> Grammar {
+		let mut text: Vec<String> = DATA_GRAMMAR.lines().map(|line| {
+			if line.starts_with("NUMBER") { return format!("NUMBER  = /{}/", number); }
+			if line.starts_with("STRING") { return format!("STRING  = /{}/", string); }
+			if line.starts_with("PTR") { return format!("PTR     = /@{}/", string); }
+			if (line.starts_with("ONEITEM") || line.starts_with("LEAF")) && quoted { return format!("{} | QSTRING", line); }
+			line.to_string()
+		}).collect();
+		if quoted { text.push("QSTRING = /\"([^\"\\\\]|\\\\.)*\"/".to_string()); }
+		if keys {
+			let at = text.iter().position(|line| line.starts_with("ITEMS")).unwrap();
+			text[at] = "ITEMS   ::= ITEM MORE".to_string();
+			let at = text.iter().position(|line| line.starts_with("LAST")).unwrap();
+			text[at] = "LAST    ::= INNER | LEAF REST | KEY '=' INNER | KEY '=' LEAF REST".to_string();
+			text.push("ITEM    ::= ONEITEM | PAIR".to_string());
+			text.push("PAIR    ::= KEY '=' ONEITEM".to_string());
+			text.push("KEY     ::= STRING".to_string());
+		}
+		Grammar::parse(&text.join("\n"), "data.grammar").expect("dialect grammar is valid")
+	}
+
+	// the same grammar (start symbol and token classes) with other rules, as a transform leaves it
+	pub fn with_rules (&self, nonterminals: Vec<String>, productions: Vec<Production>) -> Grammar {
+		let mut grammar = Grammar { start: self.start.clone(), nonterminals, productions, classes: self.classes.clone(), alternatives: HashMap::new() };
+		grammar.index();
+		grammar
+	}
+
+	// rebuild the lhs -> alternatives index after the productions change
+	fn index (&mut self) {
+		self.alternatives.clear();
+		for (n, production) in self.productions.iter().enumerate() {
+			self.alternatives.entry(production.lhs.clone()).or_insert(vec![]).push(n);
+		}
+	}
+
+	// the production indices of a nonterminal's alternatives
+	pub fn alternatives (&self, nonterminal: &str) -> &[usize] {
+		self.alternatives.get(nonterminal).map(|a| &**a).unwrap_or(&[])
+	}
+
+	// SAMPLE: the token texts of a random sentence, or None if the start symbol derives nothing
+	// Past 'depth' levels every nonterminal takes the alternative that finishes soonest
+	pub fn sample (&self, rng: &mut Rng, depth: usize) -> Option<Vec<String>> {
+		// the height of the shallowest derivation tree of each nonterminal
+		// (a production's height is one more than its tallest nonterminal's, or usize::MAX while unknown)
+		let mut heights: HashMap<String, usize> = HashMap::new();
+		let height = |heights: &HashMap<String, usize>, p: usize| self.productions[p].rhs.iter().map(|s| match *s {
+			Symbol::Nonterminal(ref n) => heights.get(n).map_or(usize::MAX, |h| h + 1),
+			_ => 1
+		}).max().unwrap_or(1);
+		let mut changed = true;
+		while changed {
+			changed = false;
+			for p in 0..self.productions.len() {
+				let found = height(&heights, p);
+				let lhs = &self.productions[p].lhs;
+				if found != usize::MAX && heights.get(lhs).is_none_or(|&h| found < h) { heights.insert(lhs.clone(), found); changed = true; }
+			}
+		}
+		if !heights.contains_key(&self.start) { return None; }
+		let height = |p: usize| height(&heights, p);
+		let mut out: Vec<String> = vec![];
+		let mut stack: Vec<(Symbol, usize)> = vec![(Symbol::Nonterminal(self.start.clone()), 0)];
+		while let Some((symbol, level)) = stack.pop() {
+			match symbol {
+				Symbol::Nonterminal(name) => {
+					let usable: Vec<usize> = self.alternatives(&name).iter().cloned().filter(|&p| height(p) != usize::MAX).collect();
+					let p = if level < depth { usable[rng.below(usable.len())] } else { *usable.iter().min_by_key(|&&p| height(p)).unwrap() };
+					for symbol in self.productions[p].rhs.iter().rev() { stack.push((symbol.clone(), level + 1)); }
+				},
+				Symbol::Literal(text) => out.push(text),
+				Symbol::Class(name) => out.push(self.classes.iter().find(|c| c.name == name).unwrap().sample(rng))
+			}
+		}
+		Some(out)
+	}
+
+	// the distinct literal terminals, in order of first use
+	pub fn literals (&self) -> Vec<String> {
+		let mut literals: Vec<String> = vec![];
+		for production in &self.productions {
+			for symbol in &production.rhs {
+				if let Symbol::Literal(ref text) = *symbol { if !literals.contains(text) { literals.push(text.clone()); } }
+			}
+		}
+		literals
+	}
+
+	// NULLABLE: the nonterminals that can derive the empty string
+	pub fn nullable (&self) -> HashSet<String> {
+		let mut nullable: HashSet<String> = HashSet::new();
+		let mut changed = true;
+		while changed {
+			changed = false;
+			for production in &self.productions {
+				if nullable.contains(&production.lhs) { continue; }
+				let all = production.rhs.iter().all(|s| match *s { Symbol::Nonterminal(ref n) => nullable.contains(n), _ => false });
+				if all { nullable.insert(production.lhs.clone()); changed = true; }
+			}
+		}
+		nullable
+	}
+
+	// a nonterminal that can derive a string starting with itself, if there is one
+	// (the left corner of a production is any symbol with only nullable nonterminals before it)
+	pub fn left_recursive (&self) -> Option<String> {
+		let nullable = self.nullable();
+		let mut corners: HashMap<&str, Vec<&str>> = HashMap::new();
+		for production in &self.productions {
+			for symbol in &production.rhs {
+				match *symbol {
+					Symbol::Nonterminal(ref name) => {
+						corners.entry(&*production.lhs).or_insert(vec![]).push(&**name);
+						if !nullable.contains(name) { break; }
+					},
+					_ => break
+				}
+			}
+		}
+		for nonterminal in &self.nonterminals {
+			let mut seen: HashSet<&str> = HashSet::new();
+			let mut stack: Vec<&str> = corners.get(&**nonterminal).cloned().unwrap_or(vec![]);
+			while let Some(next) = stack.pop() {
+				if next == nonterminal { return Some(nonterminal.clone()); }
+				if seen.insert(next) { stack.extend(corners.get(next).cloned().unwrap_or(vec![])); }
+			}
+		}
+		None
+	}
+
+	// LEXER: split the input into tokens, skipping whitespace and taking the longest match each time
+	pub fn tokenize (&self, input: &str) -> Result<Vec<Token>, String> {
+		let chars: Vec<char> = input.chars().collect();
+		let mut offsets: Vec<usize> = input.char_indices().map(|(o, _)| o).collect();
+		offsets.push(input.len());
+		let literals: Vec<Vec<char>> = self.literals().iter().map(|l| l.chars().collect()).collect();
+		let mut tokens: Vec<Token> = vec![];
+		let mut pos = 0;
+		while pos < chars.len() {
+			if chars[pos].is_whitespace() { pos += 1; continue; }
+			let mut best: Option<(usize, Symbol)> = None;
+			for literal in &literals {
+				if chars[pos..].starts_with(&literal[..]) && best.as_ref().is_none_or(|b| pos + literal.len() > b.0) {
+					best = Some((pos + literal.len(), Symbol::Literal(literal.iter().cloned().collect())));
+				}
+			}
+			for class in &self.classes {
+				if let Some(&end) = class.pattern.ends(&chars, pos).last() {
+					if end > pos && best.as_ref().is_none_or(|b| end > b.0) { best = Some((end, Symbol::Class(class.name.clone()))); }
+				}
+			}
+			match best {
+				Some((end, kind)) => {
+					tokens.push(Token { kind, text: input[offsets[pos]..offsets[end]].to_string(), start: offsets[pos], end: offsets[end] });
+					pos = end;
+				},
+				None => return Err(format!("no token matches at offset {} ('{}')", offsets[pos], chars[pos]))
+			}
+		}
+		Ok(tokens)
+	}
+
+	// PARSE: tokenize 'input' and parse it from the start symbol with the backtracking parser
+	// Every alternative is tried at every position (memoized), so any grammar without left
+	// recursion works, ambiguous ones included; the first parse found is returned
+	pub fn parse_input (&self, input: &str) -> Result<Tree, String> {
+		if let Some(name) = self.left_recursive() {
+			return Err(format!("{} is left-recursive, which the backtracking parser cannot handle", name));
+		}
+		let tokens = self.tokenize(input)?;
+		let mut parser = Backtrack { grammar: self, tokens: &tokens, memo: HashMap::new(), furthest: 0 };
+		let results = parser.nonterminal(&self.start, 0);
+		for &(end, ref tree) in results.iter() {
+			if end == tokens.len() { return Ok(tree.tree()); }
+		}
+		Err(unexpected(&tokens, parser.furthest))
+	}
+
+	// render a parse tree as indented text, one production or token per line
+	pub fn render_tree (&self, tree: &Tree) -> String {
+		let mut out = String::new();
+		let mut rest: Vec<(&Tree, usize)> = vec![(tree, 0)];
+		while let Some((tree, depth)) = rest.pop() {
+			for _ in 0..depth { out.push_str("  "); }
+			match *tree {
+				Tree::Leaf(ref token) => out.push_str(&format!("{} {:?}\n", token.kind, token.text)),
+				Tree::Node(p, ref children) => {
+					out.push_str(&format!("{}\n", self.productions[p]));
+					rest.extend(children.iter().rev().map(|child| (child, depth + 1)));
+				}
+			}
+		}
+		out
+	}
+}
+
+// the error for a parse that could not get past token 'at'
+pub fn unexpected (tokens: &[Token], at: usize) -> String {
+	if at >= tokens.len() { return "unexpected end of input".to_string(); }
+	format!("unexpected {:?} at offset {}", tokens[at].text, tokens[at].start)
+}
+
+// the trees the backtracking parser builds, with shared children: the ways of matching an
+// alternative that begin alike share the trees for that beginning, and a memoized result is
+// shared by every attempt that uses it, so nothing is copied until the parse returns its tree
+enum Built { Leaf(Token), Node(usize, Children) }
+
+// a node's children from the last one back, so adding one more shares all the ones before it
+struct Link {
+	last: Rc<Built>,
+	before: Children
+}
+
+type Children = Option<Rc<Link>>;
+
+// the (end, tree) results of a nonterminal at a position, shared by every attempt that uses them
+type Results = Rc<Vec<(usize, Rc<Built>)>>;
+
+impl Built {
+	// the tree this stands for, with a copy of each shared subtree wherever it is used
+	fn tree (&self) -> Tree {
+		// the nodes being made, each with its children still to make (first on top) and those made
+		let mut open: Vec<(usize, Vec<&Built>, Vec<Tree>)> = vec![];
+		let mut next = self;
+		loop {
+			let mut made = match *next {
+				Built::Leaf(ref token) => Some(Tree::Leaf(token.clone())),
+				Built::Node(p, ref children) => {
+					let mut todo: Vec<&Built> = vec![];
+					let mut link = children;
+					while let Some(ref l) = *link { todo.push(&l.last); link = &l.before; }
+					open.push((p, todo, vec![]));
+					None
+				}
+			};
+			loop {
+				let top = match open.last_mut() { Some(top) => top, None => return made.unwrap() };
+				if let Some(tree) = made.take() { top.2.push(tree); }
+				if let Some(child) = top.1.pop() { next = child; break; }
+				let (p, _, children) = open.pop().unwrap();
+				made = Some(Tree::Node(p, children));
+			}
+		}
+	}
+}
+
+// dropping takes apart only what nothing else shares, one level at a time, so no depth of tree
+// can overflow the stack
+impl Drop for Built {
+	fn drop (&mut self) {
+		let mut links: Vec<Rc<Link>> = match *self { Built::Node(_, ref mut children) => children.take().into_iter().collect(), _ => return };
+		while let Some(link) = links.pop() {
+			let Link { last, before } = match Rc::try_unwrap(link) { Ok(link) => link, Err(_) => continue };
+			links.extend(before);
+			if let Ok(Built::Node(_, ref mut children)) = Rc::try_unwrap(last) { links.extend(children.take()); }
+		}
+	}
+}
+
+// the backtracking parser's state: results are memoized per (nonterminal, position)
+struct Backtrack<'a> {
+	grammar: &'a Grammar,
+	tokens: &'a [Token],
+	memo: HashMap<(String, usize), Results>,
+	// the furthest token any attempt reached, for the error message
+	furthest: usize
+}
+
+// a nonterminal being matched at a position: the alternative and symbol it has got to, all the
+// ways the alternative's symbols so far can be matched (position, children), how many of those
+// have been taken one symbol further (into 'next'), and the (end, tree) results found so far
+struct Attempt {
+	name: String,
+	pos: usize,
+	alternative: usize,
+	symbol: usize,
+	partial: Vec<(usize, Children)>,
+	taken: usize,
+	next: Vec<(usize, Children)>,
+	results: Vec<(usize, Rc<Built>)>
+}
+
+impl Attempt {
+	fn new (name: &str, pos: usize) -> Attempt {
+		Attempt { name: name.to_string(), pos, alternative: 0, symbol: 0, partial: vec![(pos, None)], taken: 0, next: vec![], results: vec![] }
+	}
+}
+
+impl<'a> Backtrack<'a> {
+	// every (end, tree) for 'name' starting at token 'pos', one tree per end position
+	// The attempts waiting on an inner nonterminal are kept on an explicit stack: when one needs a
+	// result that is not memoized yet, the attempt for it goes on top, and the waiting one picks
+	// up where it was once that is done (so no depth of input can overflow the call stack)
+	fn nonterminal (&mut self, name: &str, pos: usize) -> Results {
+		if let Some(found) = self.memo.get(&(name.to_string(), pos)) { return found.clone(); }
+		let grammar = self.grammar;
+		let mut attempts: Vec<Attempt> = vec![Attempt::new(name, pos)];
+		loop {
+			let attempt = attempts.last_mut().unwrap();
+			let alternatives = grammar.alternatives(&attempt.name);
+			if attempt.alternative == alternatives.len() {
+				let done = attempts.pop().unwrap();
+				let results = Rc::new(done.results);
+				self.memo.insert((done.name, done.pos), results.clone());
+				if attempts.is_empty() { return results; }
+				continue;
+			}
+			let p = alternatives[attempt.alternative];
+			let rhs = &grammar.productions[p].rhs;
+			if attempt.symbol == rhs.len() {
+				// the whole alternative matched: a result for each end not already found
+				for (end, children) in attempt.partial.drain(..) {
+					if end > self.furthest { self.furthest = end; }
+					if !attempt.results.iter().any(|r| r.0 == end) { attempt.results.push((end, Rc::new(Built::Node(p, children)))); }
+				}
+				attempt.alternative += 1;
+				attempt.symbol = 0;
+				attempt.partial = vec![(attempt.pos, None)];
+				continue;
+			}
+			if attempt.taken == attempt.partial.len() {
+				attempt.partial = std::mem::take(&mut attempt.next);
+				attempt.taken = 0;
+				attempt.symbol += 1;
+				continue;
+			}
+			let at = attempt.partial[attempt.taken].0;
+			if at > self.furthest { self.furthest = at; }
+			match rhs[attempt.symbol] {
+				Symbol::Nonterminal(ref inner) => {
+					let found = match self.memo.get(&(inner.clone(), at)) {
+						Some(found) => found.clone(),
+						None => { attempts.push(Attempt::new(inner, at)); continue; }
+					};
+					for &(end, ref tree) in found.iter() {
+						if attempt.next.iter().any(|n| n.0 == end) { continue; }
+						let more = Link { last: tree.clone(), before: attempt.partial[attempt.taken].1.clone() };
+						attempt.next.push((end, Some(Rc::new(more))));
+					}
+				},
+				ref symbol => {
+					if at < self.tokens.len() && self.tokens[at].kind == *symbol && !attempt.next.iter().any(|n| n.0 == at + 1) {
+						let more = Link { last: Rc::new(Built::Leaf(self.tokens[at].clone())), before: attempt.partial[attempt.taken].1.take() };
+						attempt.next.push((at + 1, Some(Rc::new(more))));
+					}
+				}
+			}
+			attempt.taken += 1;
+		}
+	}
+}
+
+// COMMAND: wls254 parse [--grammar FILE] [--lines] [--tree] < input
+// Parses stdin as one sentence (or each line as one with --lines) against the grammar, the bundled
+// data-line grammar by default; prints "ok" or the error for each, and the parse tree with --tree
+pub fn parse_command (args: &[String]) -> i32 {
+	let mut grammar_file: Option<String> = None;
+	let mut engine = "backtrack".to_string();
+	let (mut lines, mut tree, mut forest, mut table) = (false, false, false, false);
+	let mut parses: usize = 0;
+	let mut views = Views { derivation: false, cst: false, dot: false };
+	let mut i = 0;
+	while i < args.len() {
+		match &*args[i] {
+			"--grammar" if i + 1 < args.len() => { grammar_file = Some(args[i+1].clone()); i += 1; },
+			"--engine" if i + 1 < args.len() && ["backtrack", "earley", "ll1", "lalr", "cyk"].contains(&&*args[i+1]) => { engine = args[i+1].clone(); i += 1; },
+			"--parses" if i + 1 < args.len() && args[i+1].parse::<usize>().is_ok() => { parses = args[i+1].parse().unwrap(); i += 1; },
+			"--lines" => lines = true,
+			"--tree" => tree = true,
+			"--trace" => views.derivation = true,
+			"--cst" => views.cst = true,
+			"--dot" => views.dot = true,
+			"--forest" => forest = true,
+			"--table" => table = true,
+			_ => { eprintln!("usage: wls254 parse [--grammar FILE] [--engine backtrack|earley|ll1|lalr|cyk] [--lines] [--tree] [--trace] [--cst] [--dot] [--forest] [--parses N] [--table] < input"); return 2; }
+		}
+		i += 1;
+	}
+	if engine != "earley" && (forest || parses > 0) { eprintln!("--forest and --parses need --engine earley"); return 2; }
+	if engine != "cyk" && table { eprintln!("--table needs --engine cyk"); return 2; }
+	if engine == "cyk" && (tree || views.any()) { eprintln!("the cyk engine only recognizes; it has no parse tree to show"); return 2; }
+	let grammar = match grammar_file {
+		Some(path) => match Grammar::load(&path) { Ok(g) => g, Err(message) => { eprintln!("{}", message); return 2; } },
+		None => Grammar::data()
+	};
+	let mut input = String::new();
+	if std::io::Read::read_to_string(&mut std::io::stdin(), &mut input).is_err() { eprintln!("cannot read stdin"); return 2; }
+	let sentences: Vec<&str> = if lines { input.lines().collect() } else { vec![&*input] };
+	let mut status = 0;
+	let cnf = if engine == "cyk" {
+		match to_cnf(&grammar) { Ok(cnf) => Some(cnf), Err(message) => { eprintln!("{}", message); return 2; } }
+	} else { None };
+	for (n, sentence) in sentences.into_iter().enumerate() {
+		if engine == "earley" {
+			if !parse_earley(&grammar, sentence, tree, views, forest, parses, n + 1) { status = 1; }
+			continue;
+		}
+		if let Some(ref cnf) = cnf {
+			// the input is split into tokens by the original grammar, whose literals decide ties
+			let tokens = match grammar.tokenize(sentence) { Ok(tokens) => tokens, Err(message) => { println!("error: {}", message); status = 1; continue; } };
+			let (accepted, cells) = cyk(cnf, &tokens);
+			if accepted { println!("ok"); } else { println!("error: not a sentence of the grammar"); status = 1; }
+			if table { print!("{}", cells.render(&tokens)); }
+			continue;
+		}
+		let parsed = match &*engine {
+			"ll1" => ll1::parse(&grammar, sentence),
+			"lalr" => lalr::parse(&grammar, sentence),
+			_ => grammar.parse_input(sentence)
+		};
+		match parsed {
+			Ok(parsed) => {
+				println!("ok");
+				if tree { print!("{}", grammar.render_tree(&parsed)); }
+				print!("{}", describe(&grammar, &parsed, sentence, views, &format!("line{}", n + 1)));
+			},
+			Err(message) => { println!("error: {}", message); status = 1; }
+		}
+	}
+	status
+}
+
+// one sentence through the Earley engine: ok or the error, any ambiguities, then what was asked for
+fn parse_earley (grammar: &Grammar, sentence: &str, tree: bool, views: Views, forest: bool, parses: usize, n: usize) -> bool {
+	let chart = match Earley::run(grammar, sentence) {
+		Ok(chart) => chart,
+		Err(message) => { println!("error: {}", message); return false; }
+	};
+	if !chart.accepted() { println!("error: {}", chart.error()); return false; }
+	println!("ok");
+	let packed = chart.forest();
+	for line in packed.ambiguities(grammar, &chart.tokens, sentence) { println!("{}", line); }
+	if forest { print!("{}", packed.render(grammar, &chart.tokens)); }
+	let trees = packed.trees(&chart.tokens, if parses > 0 { parses } else { 1 });
+	if parses > 0 {
+		for (n, parsed) in trees.iter().enumerate() {
+			println!("parse {}:", n + 1);
+			print!("{}", grammar.render_tree(parsed));
+		}
+	} else if tree {
+		if let Some(parsed) = trees.first() { print!("{}", grammar.render_tree(parsed)); }
+	}
+	if let Some(parsed) = trees.first() { print!("{}", describe(grammar, parsed, sentence, views, &format!("line{}", n))); }
+	true
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn wide_lines_parse_with_shared_partial_trees () {
+		let data = Grammar::data();
+		let numbers: Vec<String> = (0..300).map(|n| n.to_string()).collect();
+		let tree = data.parse_input(&format!("{{a: {}}}", numbers.join(", "))).unwrap();
+		let leaves = data.render_tree(&tree).lines().filter(|l| l.trim_start().starts_with("NUMBER \"")).count();
+		assert_eq!(leaves, 300);
+		// a '::=' is found after chars of any width
+		let grammar = Grammar::parse("A ::= '\u{e9}' | '\u{1f600}' '::=' B\nB ::= 'b'\n", "test").unwrap();
+		assert!(grammar.parse_input("\u{1f600} ::= b").is_ok());
+	}
+}
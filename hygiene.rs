@@ -0,0 +1,321 @@
+// Grammar hygiene: the problems cleaned up by hand before writing a parser like check_data
+// (unreachable and unproductive nonterminals, left recursion, duplicate alternatives), and
+// transforms that clean them up: useless-symbol removal, left-recursion elimination and left
+// factoring. A transform gives back a new Grammar, which prints as a grammar file.
+use std::collections::{HashMap, HashSet, VecDeque};
+use super::grammar::{Grammar, Production, Symbol};
+
+// the rules as an editable table: nonterminals in order, and each one's alternatives
+pub type Rules = (Vec<String>, HashMap<String, Vec<Vec<Symbol>>>);
+
+pub fn rules_of (grammar: &Grammar) -> Rules {
+	let mut alternatives: HashMap<String, Vec<Vec<Symbol>>> = HashMap::new();
+	for nonterminal in &grammar.nonterminals {
+		alternatives.insert(nonterminal.clone(), grammar.alternatives(nonterminal).iter().map(|&p| grammar.productions[p].rhs.clone()).collect());
+	}
+	(grammar.nonterminals.clone(), alternatives)
+}
+
+pub fn grammar_of (grammar: &Grammar, rules: Rules) -> Grammar {
+	let (order, mut alternatives) = rules;
+	let mut productions: Vec<Production> = vec![];
+	for nonterminal in &order {
+		for rhs in alternatives.remove(nonterminal).unwrap_or(vec![]) { productions.push(Production { lhs: nonterminal.clone(), rhs }); }
+	}
+	grammar.with_rules(order, productions)
+}
+
+// a name for a new nonterminal, based on an old one and not already taken
+pub fn fresh (base: &str, taken: &[String]) -> String {
+	let mut name = base.to_string();
+	let mut n = 1;
+	while taken.contains(&name) { n += 1; name = format!("{}{}", base, n); }
+	name
+}
+
+// the nonterminals reachable from the start symbol
+pub fn reachable (grammar: &Grammar) -> HashSet<String> {
+	let mut seen: HashSet<String> = HashSet::new();
+	let mut stack = vec![grammar.start.clone()];
+	while let Some(name) = stack.pop() {
+		if !seen.insert(name.clone()) { continue; }
+		for &p in grammar.alternatives(&name) {
+			for symbol in &grammar.productions[p].rhs { if let Symbol::Nonterminal(ref next) = *symbol { stack.push(next.clone()); } }
+		}
+	}
+	seen
+}
+
+// the nonterminals that derive at least one string of terminals
+pub fn productive (grammar: &Grammar) -> HashSet<String> {
+	let mut productive: HashSet<String> = HashSet::new();
+	let mut changed = true;
+	while changed {
+		changed = false;
+		for production in &grammar.productions {
+			if productive.contains(&production.lhs) { continue; }
+			if production.rhs.iter().all(|s| match *s { Symbol::Nonterminal(ref n) => productive.contains(n), _ => true }) {
+				productive.insert(production.lhs.clone());
+				changed = true;
+			}
+		}
+	}
+	productive
+}
+
+// each left-recursive nonterminal with a shortest cycle of productions that leads back to it
+// (a left corner may sit behind nullable nonterminals, so 'A ::= B A' is left-recursive if B is nullable)
+pub fn left_recursion (grammar: &Grammar) -> Vec<(String, Vec<usize>)> {
+	let nullable = grammar.nullable();
+	let mut corners: HashMap<&str, Vec<(&str, usize)>> = HashMap::new();
+	for (p, production) in grammar.productions.iter().enumerate() {
+		for symbol in &production.rhs {
+			match *symbol {
+				Symbol::Nonterminal(ref name) => {
+					corners.entry(&*production.lhs).or_insert(vec![]).push((&**name, p));
+					if !nullable.contains(name) { break; }
+				},
+				_ => break
+			}
+		}
+	}
+	let mut found: Vec<(String, Vec<usize>)> = vec![];
+	for nonterminal in &grammar.nonterminals {
+		// breadth-first from the nonterminal, remembering the production used to reach each one
+		let mut previous: HashMap<&str, (&str, usize)> = HashMap::new();
+		let mut queue: VecDeque<&str> = vec![&**nonterminal].into_iter().collect();
+		let mut cycle: Option<(&str, usize)> = None;
+		'search: while let Some(name) = queue.pop_front() {
+			for &(next, p) in corners.get(name).map(|c| &**c).unwrap_or(&[]) {
+				if next == nonterminal { cycle = Some((name, p)); break 'search; }
+				if !previous.contains_key(next) { previous.insert(next, (name, p)); queue.push_back(next); }
+			}
+		}
+		if let Some((mut name, last)) = cycle {
+			let mut path = vec![last];
+			while name != nonterminal { let (from, p) = previous[name]; path.push(p); name = from; }
+			path.reverse();
+			found.push((nonterminal.clone(), path));
+		}
+	}
+	found
+}
+
+// pairs of identical alternatives: (the first, a later repeat of it)
+pub fn duplicates (grammar: &Grammar) -> Vec<(usize, usize)> {
+	let mut found: Vec<(usize, usize)> = vec![];
+	for (p, production) in grammar.productions.iter().enumerate() {
+		if let Some(first) = grammar.productions[..p].iter().position(|q| q == production) { found.push((first, p)); }
+	}
+	found
+}
+
+// the analysis as text, and how many problems it found
+pub fn report (grammar: &Grammar) -> (String, usize) {
+	let mut out = String::new();
+	let mut problems = 0;
+	let reachable = reachable(grammar);
+	let productive = productive(grammar);
+	let unreachable: Vec<&str> = grammar.nonterminals.iter().filter(|n| !reachable.contains(*n)).map(|n| &**n).collect();
+	let unproductive: Vec<&str> = grammar.nonterminals.iter().filter(|n| !productive.contains(*n)).map(|n| &**n).collect();
+	for (label, names) in [("unreachable", unreachable), ("unproductive", unproductive)] {
+		if names.is_empty() { out.push_str(&format!("{}: none\n", label)); }
+		else { out.push_str(&format!("{}: {}\n", label, names.join(" "))); problems += names.len(); }
+	}
+	let recursion = left_recursion(grammar);
+	if recursion.is_empty() { out.push_str("left recursion: none\n"); }
+	for (name, path) in &recursion {
+		let how = if path.len() == 1 { "directly" } else { "indirectly" };
+		let shown: Vec<String> = path.iter().map(|&p| format!("{}", grammar.productions[p])).collect();
+		out.push_str(&format!("left recursion: {} {}, via {}\n", name, how, shown.join(" -> ")));
+		problems += 1;
+	}
+	let repeated = duplicates(grammar);
+	if repeated.is_empty() { out.push_str("duplicate alternatives: none\n"); }
+	for &(_, p) in &repeated { out.push_str(&format!("duplicate alternative: {}\n", grammar.productions[p])); problems += 1; }
+	(out, problems)
+}
+
+// DUPLICATES: keep only the first of each set of identical alternatives
+pub fn remove_duplicates (grammar: &Grammar) -> Grammar {
+	let (order, mut alternatives) = rules_of(grammar);
+	for alts in alternatives.values_mut() {
+		let mut kept: Vec<Vec<Symbol>> = vec![];
+		for rhs in alts.drain(..) { if !kept.contains(&rhs) { kept.push(rhs); } }
+		*alts = kept;
+	}
+	grammar_of(grammar, (order, alternatives))
+}
+
+// USELESS SYMBOLS: drop the unproductive nonterminals (and the alternatives using them), then the unreachable ones
+pub fn remove_useless (grammar: &Grammar) -> Result<Grammar, String> {
+	let productive = productive(grammar);
+	if !productive.contains(&grammar.start) { return Err(format!("the start symbol {} derives no terminal string, so nothing would be left", grammar.start)); }
+	let (order, mut alternatives) = rules_of(grammar);
+	let order: Vec<String> = order.into_iter().filter(|n| productive.contains(n)).collect();
+	for alts in alternatives.values_mut() {
+		alts.retain(|rhs| rhs.iter().all(|s| match *s { Symbol::Nonterminal(ref n) => productive.contains(n), _ => true }));
+	}
+	let trimmed = grammar_of(grammar, (order, alternatives));
+	let reachable = reachable(&trimmed);
+	let (order, alternatives) = rules_of(&trimmed);
+	Ok(grammar_of(grammar, (order.into_iter().filter(|n| reachable.contains(n)).collect(), alternatives)))
+}
+
+// whether 'from' can derive a string starting with 'to', looking only at first symbols
+fn left_reaches (alternatives: &HashMap<String, Vec<Vec<Symbol>>>, from: &str, to: &str) -> bool {
+	let mut seen: HashSet<&str> = HashSet::new();
+	let mut stack = vec![from];
+	while let Some(name) = stack.pop() {
+		if name == to { return true; }
+		if !seen.insert(name) { continue; }
+		for rhs in alternatives.get(name).map(|a| &**a).unwrap_or(&[]) {
+			if let Some(Symbol::Nonterminal(next)) = rhs.first() { stack.push(&**next); }
+		}
+	}
+	false
+}
+
+// LEFT RECURSION: the textbook elimination. In definition order, each A_i ::= A_j w (j < i, where A_j
+// leads back to A_i) has A_j's alternatives substituted in; then direct recursion A ::= A a | b becomes
+// A ::= b A_tail and A_tail ::= a A_tail | %empty. Recursion hidden behind a nullable prefix is not
+// handled, and is reported if it is left over.
+pub fn eliminate_left_recursion (grammar: &Grammar) -> Result<Grammar, String> {
+	let (mut order, mut alternatives) = rules_of(grammar);
+	let original = order.clone();
+	for i in 0..original.len() {
+		let a = original[i].clone();
+		for b in &original[..i] {
+			if !left_reaches(&alternatives, b, &a) { continue; }
+			let mut substituted: Vec<Vec<Symbol>> = vec![];
+			for rhs in &alternatives[&a] {
+				if rhs.first() == Some(&Symbol::Nonterminal(b.clone())) {
+					for start in &alternatives[b] { substituted.push(start.iter().chain(rhs[1..].iter()).cloned().collect()); }
+				} else { substituted.push(rhs.clone()); }
+			}
+			alternatives.insert(a.clone(), substituted);
+		}
+		let itself = Symbol::Nonterminal(a.clone());
+		let (recursive, rest): (Vec<Vec<Symbol>>, Vec<Vec<Symbol>>) = alternatives[&a].iter().cloned().partition(|rhs| rhs.first() == Some(&itself));
+		if recursive.is_empty() { continue; }
+		if rest.is_empty() { return Err(format!("every alternative of {} is left-recursive, so it derives nothing (remove useless symbols first)", a)); }
+		// 'A ::= A' alone adds nothing, so it is dropped rather than given a tail
+		let recursive: Vec<Vec<Symbol>> = recursive.into_iter().filter(|rhs| rhs.len() > 1).collect();
+		if recursive.is_empty() { alternatives.insert(a.clone(), rest); continue; }
+		let tail = fresh(&format!("{}_tail", a), &order);
+		let position = order.iter().position(|n| *n == a).unwrap();
+		order.insert(position + 1, tail.clone());
+		alternatives.insert(a.clone(), rest.into_iter().map(|mut rhs| { rhs.push(Symbol::Nonterminal(tail.clone())); rhs }).collect());
+		let mut tails: Vec<Vec<Symbol>> = recursive.into_iter().map(|rhs| {
+			let mut more: Vec<Symbol> = rhs[1..].to_vec();
+			more.push(Symbol::Nonterminal(tail.clone()));
+			more
+		}).collect();
+		tails.push(vec![]);
+		alternatives.insert(tail, tails);
+	}
+	let result = grammar_of(grammar, (order, alternatives));
+	if let Some(name) = result.left_recursive() {
+		return Err(format!("{} is still left-recursive behind a nullable prefix; remove its %empty alternatives first", name));
+	}
+	Ok(result)
+}
+
+// LEFT FACTORING: while some nonterminal has two or more alternatives starting with the same
+// symbol, replace them with A ::= prefix A_rest, where prefix is their longest common prefix
+// and A_rest has what is left of each
+pub fn left_factor (grammar: &Grammar) -> Grammar {
+	let (mut order, mut alternatives) = rules_of(&remove_duplicates(grammar));
+	let mut changed = true;
+	while changed {
+		changed = false;
+		for position in 0..order.len() {
+			let a = order[position].clone();
+			let alts = alternatives[&a].clone();
+			let shared = alts.iter().enumerate().filter_map(|(n, rhs)| {
+				let first = rhs.first()?;
+				if alts[..n].iter().any(|r| r.first() == Some(first)) { return None; }
+				if alts[n+1..].iter().any(|r| r.first() == Some(first)) { Some(first.clone()) } else { None }
+			}).next();
+			let first = match shared { Some(first) => first, None => continue };
+			let group: Vec<&Vec<Symbol>> = alts.iter().filter(|rhs| rhs.first() == Some(&first)).collect();
+			let mut prefix = group[0].len();
+			for rhs in &group { prefix = prefix.min(rhs.iter().zip(group[0].iter()).take_while(|&(x, y)| x == y).count()); }
+			let rest = fresh(&format!("{}_rest", a), &order);
+			let mut kept: Vec<Vec<Symbol>> = vec![];
+			for rhs in &alts {
+				if rhs.first() != Some(&first) { kept.push(rhs.clone()); }
+				else if kept.iter().all(|k| k.first() != Some(&first)) {
+					let mut factored: Vec<Symbol> = rhs[..prefix].to_vec();
+					factored.push(Symbol::Nonterminal(rest.clone()));
+					kept.push(factored);
+				}
+			}
+			alternatives.insert(a, kept);
+			alternatives.insert(rest.clone(), group.iter().map(|rhs| rhs[prefix..].to_vec()).collect());
+			order.insert(position + 1, rest);
+			changed = true;
+			break;
+		}
+	}
+	grammar_of(grammar, (order, alternatives))
+}
+
+// wls254 hygiene [--grammar FILE] [--remove-useless] [--remove-left-recursion] [--left-factor]
+// Without a transform, print the analysis (exit status 1 if it found problems); with any, apply
+// them in that order (duplicate alternatives are always dropped) and print the new grammar file
+pub fn hygiene_command (args: &[String]) -> i32 {
+	let mut grammar_file: Option<String> = None;
+	let (mut useless, mut recursion, mut factor) = (false, false, false);
+	let mut i = 0;
+	while i < args.len() {
+		match &*args[i] {
+			"--grammar" if i + 1 < args.len() => { grammar_file = Some(args[i+1].clone()); i += 1; },
+			"--remove-useless" => useless = true,
+			"--remove-left-recursion" => recursion = true,
+			"--left-factor" => factor = true,
+			_ => { eprintln!("usage: wls254 hygiene [--grammar FILE] [--remove-useless] [--remove-left-recursion] [--left-factor]"); return 2; }
+		}
+		i += 1;
+	}
+	let grammar = match grammar_file {
+		Some(path) => match Grammar::load(&path) { Ok(g) => g, Err(message) => { eprintln!("{}", message); return 2; } },
+		None => Grammar::data()
+	};
+	if !useless && !recursion && !factor {
+		let (out, problems) = report(&grammar);
+		print!("{}", out);
+		return if problems == 0 { 0 } else { 1 };
+	}
+	let mut result = remove_duplicates(&grammar);
+	if useless {
+		result = match remove_useless(&result) { Ok(g) => g, Err(message) => { eprintln!("{}", message); return 1; } };
+	}
+	if recursion {
+		result = match eliminate_left_recursion(&result) { Ok(g) => g, Err(message) => { eprintln!("{}", message); return 1; } };
+	}
+	if factor { result = left_factor(&result); }
+	print!("{}", result);
+	0
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn problems_are_found_and_transforms_remove_them () {
+		let messy = Grammar::parse("S ::= A 'x' | B | S 'y' | A 'x'\nA ::= B 'a' | 'c'\nB ::= A 'b' | 'd'\nC ::= 'z'\nD ::= D 'q'\nE ::= 'e' | D\n", "messy.grammar").unwrap();
+		let (out, problems) = report(&messy);
+		assert_eq!(problems, 9);
+		assert!(out.starts_with("unreachable: C D E\nunproductive: D\nleft recursion: S directly, via S ::= S 'y'\n"), "{}", out);
+		assert!(out.contains("left recursion: A indirectly, via A ::= B 'a' -> B ::= A 'b'\n") && out.ends_with("duplicate alternative: S ::= A 'x'\n"));
+		let clean = eliminate_left_recursion(&remove_useless(&remove_duplicates(&messy)).unwrap()).unwrap();
+		assert_eq!(report(&clean).1, 0);
+		for sentence in &["c x", "d", "c b a x y y"] { assert!(clean.parse_input(sentence).is_ok(), "{}", sentence); }
+		let left = Grammar::parse("E ::= E '+' T | T\nT = /[0-9]+/\n", "lr.grammar").unwrap();
+		assert_eq!(format!("{}", eliminate_left_recursion(&left).unwrap()), "E ::= T E_tail\nE_tail ::= '+' T E_tail | %empty\nT = /[0-9]+/\n");
+		let items = Grammar::parse("ITEMS ::= ITEM | ITEM ',' ITEMS\nITEM = /[a-z]+/\n", "items.grammar").unwrap();
+		assert!(format!("{}", left_factor(&items)).starts_with("ITEMS ::= ITEM ITEMS_rest\nITEMS_rest ::= %empty | ',' ITEMS\n"));
+	}
+}
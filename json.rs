@@ -0,0 +1,246 @@
+// Just enough JSON for the language server: a value type, a parser, and a printer
+use std::fmt;
+
+pub enum Json {
+	Null,
+	Bool(bool),
+	Number(f64),
+	Str(String),
+	Array(Vec<Json>),
+	Object(Vec<(String, Json)>)
+}
+
+impl Clone for Json {
+	fn clone (&self) -> Json {
+		match *self {
+			Json::Null => Json::Null,
+			Json::Bool(b) => Json::Bool(b),
+			Json::Number(n) => Json::Number(n),
+			Json::Str(ref s) => Json::Str(s.clone()),
+			Json::Array(ref a) => Json::Array(a.clone()),
+			Json::Object(ref o) => Json::Object(o.clone())
+		}
+	}
+}
+
+impl Json {
+	// look up a field of an object (None for anything else)
+	pub fn get (&self, key: &str) -> Option<&Json> {
+		match *self {
+			Json::Object(ref fields) => fields.iter().find(|f| f.0 == key).map(|f| &f.1),
+			_ => None
+		}
+	}
+
+	pub fn as_str (&self) -> Option<&str> {
+		match *self { Json::Str(ref s) => Some(&**s), _ => None }
+	}
+
+	pub fn as_usize (&self) -> Option<usize> {
+		match *self { Json::Number(n) if n >= 0.0 => Some(n as usize), _ => None }
+	}
+
+	pub fn as_bool (&self) -> Option<bool> {
+		match *self { Json::Bool(b) => Some(b), _ => None }
+	}
+
+	pub fn as_array (&self) -> Option<&Vec<Json>> {
+		match *self { Json::Array(ref a) => Some(a), _ => None }
+	}
+}
+
+// build an object from (key, value) pairs
+pub fn object (fields: Vec<(&str, Json)>) -> Json {
+	Json::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+pub fn string (s: &str) -> Json { Json::Str(s.to_string()) }
+
+pub fn number (n: usize) -> Json { Json::Number(n as f64) }
+
+// write 's' as a quoted JSON string
+pub fn write_string (f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+	write!(f, "\"")?;
+	for c in s.chars() {
+		match c {
+			'"' => write!(f, "\\\"")?,
+			'\\' => write!(f, "\\\\")?,
+			'\n' => write!(f, "\\n")?,
+			'\r' => write!(f, "\\r")?,
+			'\t' => write!(f, "\\t")?,
+			c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+			c => write!(f, "{}", c)?
+		}
+	}
+	write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+	fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Json::Null => write!(f, "null"),
+			Json::Bool(b) => write!(f, "{}", b),
+			Json::Number(n) => {
+				if n == n.trunc() && n.abs() < 1e15 { write!(f, "{}", n as i64) } else { write!(f, "{}", n) }
+			},
+			Json::Str(ref s) => write_string(f, s),
+			Json::Array(ref a) => {
+				write!(f, "[")?;
+				for (n, value) in a.iter().enumerate() {
+					if n > 0 { write!(f, ",")?; }
+					write!(f, "{}", value)?;
+				}
+				write!(f, "]")
+			},
+			Json::Object(ref o) => {
+				write!(f, "{{")?;
+				for (n, (key, value)) in o.iter().enumerate() {
+					if n > 0 { write!(f, ",")?; }
+					write_string(f, key)?;
+					write!(f, ":{}", value)?;
+				}
+				write!(f, "}}")
+			}
+		}
+	}
+}
+
+// arrays and objects may nest this deep in a message; no LSP message comes close, and a client
+// sending deeper ones gets a parse error instead of overflowing the stack
+const MAX_DEPTH: usize = 128;
+
+// PARSE: recursive descent over the JSON grammar, returning None on any syntax error
+pub fn parse (s: &str) -> Option<Json> {
+	let chars: Vec<char> = s.chars().collect();
+	let mut pos = 0;
+	let value = parse_value(&chars, &mut pos, 0);
+	skip_whitespace(&chars, &mut pos);
+	if pos != chars.len() { return None; }
+	value
+}
+
+fn skip_whitespace (chars: &[char], pos: &mut usize) {
+	while *pos < chars.len() && chars[*pos].is_whitespace() { *pos += 1; }
+}
+
+fn expect_word (chars: &[char], pos: &mut usize, word: &str) -> bool {
+	for c in word.chars() {
+		if *pos >= chars.len() || chars[*pos] != c { return false; }
+		*pos += 1;
+	}
+	true
+}
+
+// 'depth' is how many arrays and objects the value is inside
+fn parse_value (chars: &[char], pos: &mut usize, depth: usize) -> Option<Json> {
+	skip_whitespace(chars, pos);
+	if *pos >= chars.len() { return None; }
+	if depth == MAX_DEPTH && (chars[*pos] == '[' || chars[*pos] == '{') { return None; }
+	match chars[*pos] {
+		'n' => if expect_word(chars, pos, "null") { Some(Json::Null) } else { None },
+		't' => if expect_word(chars, pos, "true") { Some(Json::Bool(true)) } else { None },
+		'f' => if expect_word(chars, pos, "false") { Some(Json::Bool(false)) } else { None },
+		'"' => parse_string(chars, pos).map(Json::Str),
+		'[' => {
+			*pos += 1;
+			let mut items: Vec<Json> = vec![];
+			skip_whitespace(chars, pos);
+			if *pos < chars.len() && chars[*pos] == ']' { *pos += 1; return Some(Json::Array(items)); }
+			loop {
+				items.push(parse_value(chars, pos, depth + 1)?);
+				skip_whitespace(chars, pos);
+				if *pos >= chars.len() { return None; }
+				*pos += 1;
+				match chars[*pos-1] { ',' => {}, ']' => return Some(Json::Array(items)), _ => return None }
+			}
+		},
+		'{' => {
+			*pos += 1;
+			let mut fields: Vec<(String, Json)> = vec![];
+			skip_whitespace(chars, pos);
+			if *pos < chars.len() && chars[*pos] == '}' { *pos += 1; return Some(Json::Object(fields)); }
+			loop {
+				skip_whitespace(chars, pos);
+				if *pos >= chars.len() || chars[*pos] != '"' { return None; }
+				let key = parse_string(chars, pos)?;
+				skip_whitespace(chars, pos);
+				if *pos >= chars.len() || chars[*pos] != ':' { return None; }
+				*pos += 1;
+				fields.push((key, parse_value(chars, pos, depth + 1)?));
+				skip_whitespace(chars, pos);
+				if *pos >= chars.len() { return None; }
+				*pos += 1;
+				match chars[*pos-1] { ',' => {}, '}' => return Some(Json::Object(fields)), _ => return None }
+			}
+		},
+		_ => {
+			let start = *pos;
+			while *pos < chars.len() && (chars[*pos].is_ascii_digit() || "+-.eE".contains(chars[*pos])) { *pos += 1; }
+			let text: String = chars[start..*pos].iter().cloned().collect();
+			text.parse::<f64>().ok().map(Json::Number)
+		}
+	}
+}
+
+// a quoted string, with \uXXXX escapes (and surrogate pairs) decoded
+fn parse_string (chars: &[char], pos: &mut usize) -> Option<String> {
+	*pos += 1;
+	let mut s = String::new();
+	while *pos < chars.len() {
+		let c = chars[*pos];
+		*pos += 1;
+		match c {
+			'"' => return Some(s),
+			'\\' => {
+				if *pos >= chars.len() { return None; }
+				*pos += 1;
+				match chars[*pos-1] {
+					'"' => s.push('"'),
+					'\\' => s.push('\\'),
+					'/' => s.push('/'),
+					'b' => s.push('\u{8}'),
+					'f' => s.push('\u{c}'),
+					'n' => s.push('\n'),
+					'r' => s.push('\r'),
+					't' => s.push('\t'),
+					'u' => {
+						let high = parse_hex4(chars, pos)?;
+						if (0xD800..0xDC00).contains(&high) {
+							if !expect_word(chars, pos, "\\u") { return None; }
+							let low = parse_hex4(chars, pos)?;
+							if !(0xDC00..=0xDFFF).contains(&low) { return None; }
+							s.push(std::char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))?);
+						} else { s.push(std::char::from_u32(high)?); }
+					},
+					_ => return None
+				}
+			},
+			c => s.push(c)
+		}
+	}
+	None
+}
+
+fn parse_hex4 (chars: &[char], pos: &mut usize) -> Option<u32> {
+	if *pos + 4 > chars.len() { return None; }
+	let text: String = chars[*pos..*pos+4].iter().cloned().collect();
+	*pos += 4;
+	u32::from_str_radix(&text, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn messages_parse_up_to_the_nesting_cap () {
+		let message = parse("{\"id\": 1, \"params\": {\"text\": \"a\\u00e9\\n\", \"list\": [true, null, -2.5e1]}}").unwrap();
+		let params = message.get("params").unwrap();
+		assert_eq!((message.get("id").and_then(Json::as_usize), params.get("text").and_then(Json::as_str)), (Some(1), Some("a\u{e9}\n")));
+		assert_eq!(params.get("list").and_then(Json::as_array).map(|a| a.len()), Some(3));
+		let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+		assert!(parse(&nested(MAX_DEPTH)).is_some());
+		assert!(parse(&nested(MAX_DEPTH + 1)).is_none());
+		assert!(parse(&nested(100_000)).is_none());
+	}
+}
@@ -0,0 +1,348 @@
+// LR analysis of a loaded grammar: the LR(0) automaton, LALR(1) lookaheads on top of it, the
+// action table with its shift/reduce and reduce/reduce conflicts, and a table-driven shift-reduce parser
+//
+// The lookaheads are found by propagation to a fixpoint over the LR(0) states (closure spreads
+// FIRST of what follows a nonterminal, goto carries an item's lookaheads to the advanced item),
+// which gives the same sets as merging the canonical LR(1) states that share a core.
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use super::grammar::{Grammar, Production, Symbol, Token, Tree, unexpected};
+use super::ll1::{Lookahead, first_of, first_sets, show_lookahead};
+
+// an LR(0) item: a production and how much of its rhs has been seen
+type Item = (usize, usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Action {
+	Shift(usize),
+	Reduce(usize),
+	Accept
+}
+
+pub struct State {
+	// the kernel items first, then the ones the closure added
+	pub items: Vec<Item>,
+	pub goto: BTreeMap<Symbol, usize>,
+	pub lookaheads: HashMap<Item, BTreeSet<Lookahead>>,
+	pub actions: BTreeMap<Lookahead, Vec<Action>>
+}
+
+pub struct Automaton {
+	// the grammar's productions plus the augmented start production '$accept ::= start', last
+	pub productions: Vec<Production>,
+	pub states: Vec<State>
+}
+
+impl Automaton {
+	pub fn build (grammar: &Grammar) -> Automaton {
+		let mut productions = grammar.productions.clone();
+		let accept = productions.len();
+		productions.push(Production { lhs: "$accept".to_string(), rhs: vec![Symbol::Nonterminal(grammar.start.clone())] });
+
+		// LR(0): states are identified by their kernels
+		let closure = |kernel: &Vec<Item>| -> Vec<Item> {
+			let mut items = kernel.clone();
+			let mut i = 0;
+			while i < items.len() {
+				let (p, dot) = items[i];
+				i += 1;
+				if let Some(Symbol::Nonterminal(name)) = productions[p].rhs.get(dot) {
+					for &q in grammar.alternatives(name) { if !items.contains(&(q, 0)) { items.push((q, 0)); } }
+				}
+			}
+			items
+		};
+		let mut states: Vec<State> = vec![];
+		let mut kernels: HashMap<Vec<Item>, usize> = HashMap::new();
+		let start = vec![(accept, 0)];
+		kernels.insert(start.clone(), 0);
+		states.push(State { items: closure(&start), goto: BTreeMap::new(), lookaheads: HashMap::new(), actions: BTreeMap::new() });
+		let mut s = 0;
+		while s < states.len() {
+			let mut moves: BTreeMap<Symbol, Vec<Item>> = BTreeMap::new();
+			for &(p, dot) in &states[s].items {
+				if let Some(symbol) = productions[p].rhs.get(dot) { moves.entry(symbol.clone()).or_insert(vec![]).push((p, dot + 1)); }
+			}
+			for (symbol, mut kernel) in moves {
+				kernel.sort();
+				let target = match kernels.get(&kernel) {
+					Some(&t) => t,
+					None => {
+						states.push(State { items: closure(&kernel), goto: BTreeMap::new(), lookaheads: HashMap::new(), actions: BTreeMap::new() });
+						kernels.insert(kernel, states.len() - 1);
+						states.len() - 1
+					}
+				};
+				states[s].goto.insert(symbol, target);
+			}
+			s += 1;
+		}
+
+		// LALR(1) lookaheads
+		let nullable = grammar.nullable();
+		let first = first_sets(grammar, &nullable);
+		states[0].lookaheads.insert((accept, 0), vec![None].into_iter().collect());
+		let mut changed = true;
+		while changed {
+			changed = false;
+			for s in 0..states.len() {
+				let mut spread: Vec<(usize, Item, BTreeSet<Lookahead>)> = vec![];
+				for &(p, dot) in &states[s].items {
+					let current = states[s].lookaheads.get(&(p, dot)).cloned().unwrap_or(BTreeSet::new());
+					match productions[p].rhs.get(dot) {
+						Some(Symbol::Nonterminal(name)) => {
+							let (rest, empty) = first_of(&productions[p].rhs[dot+1..], &first, &nullable);
+							let mut found: BTreeSet<Lookahead> = rest.into_iter().map(Some).collect();
+							if empty { found.extend(current.iter().cloned()); }
+							for &q in grammar.alternatives(name) { spread.push((s, (q, 0), found.clone())); }
+							spread.push((states[s].goto[&Symbol::Nonterminal(name.clone())], (p, dot + 1), current));
+						},
+						Some(terminal) => spread.push((states[s].goto[terminal], (p, dot + 1), current)),
+						None => {}
+					}
+				}
+				for (target, item, found) in spread {
+					let set = states[target].lookaheads.entry(item).or_default();
+					let before = set.len();
+					set.extend(found);
+					if set.len() != before { changed = true; }
+				}
+			}
+		}
+
+		// ACTIONS: shift on terminals, reduce completed items on their lookaheads
+		for state in states.iter_mut() {
+			let mut actions: BTreeMap<Lookahead, Vec<Action>> = BTreeMap::new();
+			for (symbol, &target) in &state.goto {
+				if let Symbol::Nonterminal(_) = *symbol { continue; }
+				actions.entry(Some(symbol.clone())).or_insert(vec![]).push(Action::Shift(target));
+			}
+			for &(p, dot) in &state.items {
+				if dot < productions[p].rhs.len() { continue; }
+				if p == accept { actions.entry(None).or_insert(vec![]).push(Action::Accept); continue; }
+				for lookahead in state.lookaheads.get(&(p, dot)).cloned().unwrap_or(BTreeSet::new()) {
+					actions.entry(lookahead).or_insert(vec![]).push(Action::Reduce(p));
+				}
+			}
+			state.actions = actions;
+		}
+		Automaton { productions, states }
+	}
+
+	// the states that are not LR(0): a completed item next to a shift or another completed item
+	pub fn lr0_conflicts (&self) -> Vec<usize> {
+		(0..self.states.len()).filter(|&s| {
+			let state = &self.states[s];
+			let reduces = state.items.iter().filter(|&&(p, dot)| dot == self.productions[p].rhs.len()).count();
+			let shifts = state.goto.keys().any(|k| !matches!(*k, Symbol::Nonterminal(_)));
+			reduces > 1 || (reduces == 1 && shifts)
+		}).collect()
+	}
+
+	// the LALR(1) conflicts: (state, lookahead, the competing actions)
+	pub fn conflicts (&self) -> Vec<(usize, &Lookahead, &Vec<Action>)> {
+		let mut conflicts = vec![];
+		for (s, state) in self.states.iter().enumerate() {
+			for (lookahead, actions) in &state.actions { if actions.len() > 1 { conflicts.push((s, lookahead, actions)); } }
+		}
+		conflicts
+	}
+
+	pub fn show_item (&self, state: &State, item: Item) -> String {
+		let production = &self.productions[item.0];
+		let mut out = format!("{} ::=", production.lhs);
+		for (n, symbol) in production.rhs.iter().enumerate() {
+			if n == item.1 { out.push_str(" ."); }
+			out.push_str(&format!(" {}", symbol));
+		}
+		if item.1 == production.rhs.len() { out.push_str(" ."); }
+		let lookaheads: Vec<String> = state.lookaheads.get(&item).map(|l| l.iter().map(show_lookahead).collect()).unwrap_or(vec![]);
+		format!("{}  [{}]", out, lookaheads.join(" "))
+	}
+
+	pub fn show_action (&self, action: &Action) -> String {
+		match *action {
+			Action::Shift(target) => format!("shift to state {}", target),
+			Action::Reduce(p) => format!("reduce {}", self.productions[p]),
+			Action::Accept => "accept".to_string()
+		}
+	}
+
+	// a counterexample for a conflict: terminals that lead from the start to 'state', then the lookahead
+	// (each nonterminal on the way is replaced by its shortest terminal expansion)
+	pub fn counterexample (&self, target: usize, lookahead: &Lookahead) -> String {
+		let mut previous: Vec<Option<(usize, Symbol)>> = vec![None; self.states.len()];
+		let mut queue: VecDeque<usize> = vec![0].into_iter().collect();
+		let mut seen = vec![false; self.states.len()];
+		seen[0] = true;
+		while let Some(s) = queue.pop_front() {
+			for (symbol, &t) in &self.states[s].goto {
+				if !seen[t] { seen[t] = true; previous[t] = Some((s, symbol.clone())); queue.push_back(t); }
+			}
+		}
+		let mut path: Vec<Symbol> = vec![];
+		let mut s = target;
+		while let Some((from, ref symbol)) = previous[s] { path.push(symbol.clone()); s = from; }
+		path.reverse();
+		let shortest = self.shortest_expansions();
+		let mut shown: Vec<String> = vec![];
+		for symbol in &path {
+			match *symbol {
+				Symbol::Nonterminal(ref name) => match shortest.get(name) {
+					Some(expansion) => shown.extend(expansion.iter().map(|s| format!("{}", s))),
+					None => shown.push(name.clone())
+				},
+				ref terminal => shown.push(format!("{}", terminal))
+			}
+		}
+		shown.push(".".to_string());
+		shown.push(show_lookahead(lookahead));
+		shown.join(" ")
+	}
+
+	// the shortest string of terminals each (productive) nonterminal derives
+	fn shortest_expansions (&self) -> HashMap<String, Vec<Symbol>> {
+		let mut shortest: HashMap<String, Vec<Symbol>> = HashMap::new();
+		let mut changed = true;
+		while changed {
+			changed = false;
+			for production in &self.productions {
+				let mut expansion: Vec<Symbol> = vec![];
+				let mut complete = true;
+				for symbol in &production.rhs {
+					match *symbol {
+						Symbol::Nonterminal(ref name) => match shortest.get(name) {
+							Some(found) => expansion.extend(found.iter().cloned()),
+							None => { complete = false; break; }
+						},
+						ref terminal => expansion.push(terminal.clone())
+					}
+				}
+				if complete && shortest.get(&production.lhs).is_none_or(|e| expansion.len() < e.len()) {
+					shortest.insert(production.lhs.clone(), expansion);
+					changed = true;
+				}
+			}
+		}
+		shortest
+	}
+
+	// the analysis as text: a summary, every conflict with its item set and a counterexample,
+	// and with 'all_states' every state of the automaton
+	pub fn report (&self, all_states: bool) -> String {
+		let mut out = format!("states: {}\n", self.states.len());
+		if all_states {
+			for (s, state) in self.states.iter().enumerate() {
+				out.push_str(&format!("state {}:\n", s));
+				for &item in &state.items { out.push_str(&format!("  {}\n", self.show_item(state, item))); }
+				for (lookahead, actions) in &state.actions {
+					for action in actions { out.push_str(&format!("  on {}: {}\n", show_lookahead(lookahead), self.show_action(action))); }
+				}
+				for (symbol, target) in &state.goto {
+					if let Symbol::Nonterminal(_) = *symbol { out.push_str(&format!("  goto {}: state {}\n", symbol, target)); }
+				}
+			}
+		}
+		let lr0 = self.lr0_conflicts();
+		if lr0.is_empty() { out.push_str("LR(0): yes\n"); }
+		else {
+			let shown: Vec<String> = lr0.iter().map(|s| format!("{}", s)).collect();
+			out.push_str(&format!("LR(0): no, lookahead needed in state{} {}\n", if lr0.len() == 1 { "" } else { "s" }, shown.join(" ")));
+		}
+		let conflicts = self.conflicts();
+		for &(s, lookahead, actions) in &conflicts {
+			let state = &self.states[s];
+			let reduces = actions.iter().filter(|a| matches!(**a, Action::Reduce(_))).count();
+			let kind = if reduces == actions.len() { "reduce/reduce" } else { "shift/reduce" };
+			out.push_str(&format!("{} conflict in state {} on {}:\n", kind, s, show_lookahead(lookahead)));
+			for &item in &state.items { out.push_str(&format!("  {}\n", self.show_item(state, item))); }
+			for action in actions.iter() { out.push_str(&format!("  could {}\n", self.show_action(action))); }
+			out.push_str(&format!("  counterexample: {}\n", self.counterexample(s, lookahead)));
+		}
+		if conflicts.is_empty() { out.push_str("LALR(1): yes\n"); }
+		else { out.push_str(&format!("LALR(1): no, {} conflict{}\n", conflicts.len(), if conflicts.len() == 1 { "" } else { "s" })); }
+		out
+	}
+}
+
+// PARSE: tokenize 'input' and run the shift-reduce parser (refused if the table has conflicts)
+pub fn parse (grammar: &Grammar, input: &str) -> Result<Tree, String> {
+	let automaton = Automaton::build(grammar);
+	if !automaton.conflicts().is_empty() { return Err("the grammar is not LALR(1) (see wls254 lalr for its conflicts)".to_string()); }
+	let tokens: Vec<Token> = grammar.tokenize(input)?;
+	let mut states: Vec<usize> = vec![0];
+	let mut trees: Vec<Tree> = vec![];
+	let mut pos = 0;
+	loop {
+		let lookahead: Lookahead = tokens.get(pos).map(|t| t.kind.clone());
+		let action = match automaton.states[*states.last().unwrap()].actions.get(&lookahead) {
+			Some(actions) => actions[0],
+			None => return Err(unexpected(&tokens, pos))
+		};
+		match action {
+			Action::Shift(target) => {
+				trees.push(Tree::Leaf(tokens[pos].clone()));
+				states.push(target);
+				pos += 1;
+			},
+			Action::Reduce(p) => {
+				let production = &automaton.productions[p];
+				let children = trees.split_off(trees.len() - production.rhs.len());
+				states.truncate(states.len() - production.rhs.len());
+				trees.push(Tree::Node(p, children));
+				let target = automaton.states[*states.last().unwrap()].goto[&Symbol::Nonterminal(production.lhs.clone())];
+				states.push(target);
+			},
+			Action::Accept => return Ok(trees.pop().unwrap())
+		}
+	}
+}
+
+// wls254 lalr [--grammar FILE] [--states]: print the analysis; the exit status is 0 only for an LALR(1) grammar
+pub fn lalr_command (args: &[String]) -> i32 {
+	let mut grammar_file: Option<String> = None;
+	let mut all_states = false;
+	let mut i = 0;
+	while i < args.len() {
+		match &*args[i] {
+			"--grammar" if i + 1 < args.len() => { grammar_file = Some(args[i+1].clone()); i += 1; },
+			"--states" => all_states = true,
+			_ => { eprintln!("usage: wls254 lalr [--grammar FILE] [--states]"); return 2; }
+		}
+		i += 1;
+	}
+	let grammar = match grammar_file {
+		Some(path) => match Grammar::load(&path) { Ok(g) => g, Err(message) => { eprintln!("{}", message); return 2; } },
+		None => Grammar::data()
+	};
+	let automaton = Automaton::build(&grammar);
+	print!("{}", automaton.report(all_states));
+	if automaton.conflicts().is_empty() { 0 } else { 1 }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn every_data_dialect_grammar_is_lalr1 () {
+		for &quoted in &[false, true] {
+			for &keys in &[false, true] {
+				let grammar = Grammar::data_dialect("-?[0-9]+", "[a-zA-Z][0-9a-zA-Z]*", quoted, keys);
+				assert_eq!(Automaton::build(&grammar).conflicts().len(), 0, "quoted {} keys {}", quoted, keys);
+			}
+		}
+		let data = Grammar::data();
+		assert_eq!(parse(&data, "{a: 1, {b: @c}}").unwrap(), data.parse_input("{a: 1, {b: @c}}").unwrap());
+		assert!(parse(&data, "{a: {b: {c: 1}, 2}}").is_err());
+	}
+
+	#[test]
+	fn conflicts_come_with_counterexamples () {
+		let grammar = Grammar::parse("E ::= E '+' E | E '*' E | N\nN = /[0-9]+/\n", "amb.grammar").unwrap();
+		let automaton = Automaton::build(&grammar);
+		assert_eq!(automaton.conflicts().len(), 4);
+		let report = automaton.report(false);
+		assert!(report.contains("  counterexample: N '+' N . '*'\n") && report.ends_with("LALR(1): no, 4 conflicts\n"), "{}", report);
+		assert!(parse(&grammar, "1 + 2").is_err());
+	}
+}
@@ -0,0 +1,201 @@
+// LL(1) analysis of a loaded grammar: nullable, FIRST and FOLLOW sets, the predictive parse table
+// and every conflict in it, and a table-driven predictive parser for grammars without conflicts
+use std::collections::{BTreeSet, HashMap, HashSet};
+use super::grammar::{Grammar, Symbol, Token, Tree, unexpected};
+
+// a lookahead terminal, or None for the end of the input
+pub type Lookahead = Option<Symbol>;
+
+pub fn show_lookahead (lookahead: &Lookahead) -> String {
+	match *lookahead { Some(ref terminal) => format!("{}", terminal), None => "$end".to_string() }
+}
+
+// FIRST: the terminals each nonterminal's strings can start with
+pub fn first_sets (grammar: &Grammar, nullable: &HashSet<String>) -> HashMap<String, BTreeSet<Symbol>> {
+	let mut first: HashMap<String, BTreeSet<Symbol>> = HashMap::new();
+	for nonterminal in &grammar.nonterminals { first.insert(nonterminal.clone(), BTreeSet::new()); }
+	let mut changed = true;
+	while changed {
+		changed = false;
+		for production in &grammar.productions {
+			let (found, _) = first_of(&production.rhs, &first, nullable);
+			let set = first.get_mut(&production.lhs).unwrap();
+			let before = set.len();
+			set.extend(found);
+			if set.len() != before { changed = true; }
+		}
+	}
+	first
+}
+
+// FIRST of a sequence of symbols, and whether the whole sequence can derive the empty string
+pub fn first_of (symbols: &[Symbol], first: &HashMap<String, BTreeSet<Symbol>>, nullable: &HashSet<String>) -> (BTreeSet<Symbol>, bool) {
+	let mut found: BTreeSet<Symbol> = BTreeSet::new();
+	for symbol in symbols {
+		match *symbol {
+			Symbol::Nonterminal(ref name) => {
+				if let Some(set) = first.get(name) { found.extend(set.iter().cloned()); }
+				if !nullable.contains(name) { return (found, false); }
+			},
+			ref terminal => { found.insert(terminal.clone()); return (found, false); }
+		}
+	}
+	(found, true)
+}
+
+pub struct Ll1 {
+	pub nullable: HashSet<String>,
+	pub first: HashMap<String, BTreeSet<Symbol>>,
+	pub follow: HashMap<String, BTreeSet<Lookahead>>,
+	// (nonterminal, lookahead) -> the productions to predict; more than one is a conflict
+	pub table: HashMap<(String, Lookahead), Vec<usize>>
+}
+
+impl Ll1 {
+	pub fn build (grammar: &Grammar) -> Ll1 {
+		let nullable = grammar.nullable();
+		let first = first_sets(grammar, &nullable);
+
+		// FOLLOW: what can come right after each nonterminal; the start symbol is followed by the end
+		let mut follow: HashMap<String, BTreeSet<Lookahead>> = HashMap::new();
+		for nonterminal in &grammar.nonterminals { follow.insert(nonterminal.clone(), BTreeSet::new()); }
+		follow.get_mut(&grammar.start).unwrap().insert(None);
+		let mut changed = true;
+		while changed {
+			changed = false;
+			for production in &grammar.productions {
+				for (n, symbol) in production.rhs.iter().enumerate() {
+					let name = match *symbol { Symbol::Nonterminal(ref name) => name, _ => continue };
+					let (rest, rest_nullable) = first_of(&production.rhs[n+1..], &first, &nullable);
+					let mut found: BTreeSet<Lookahead> = rest.into_iter().map(Some).collect();
+					if rest_nullable { found.extend(follow[&production.lhs].iter().cloned()); }
+					let set = follow.get_mut(name).unwrap();
+					let before = set.len();
+					set.extend(found);
+					if set.len() != before { changed = true; }
+				}
+			}
+		}
+
+		// TABLE: predict A ::= w on FIRST(w), and on FOLLOW(A) too when w can be empty
+		let mut table: HashMap<(String, Lookahead), Vec<usize>> = HashMap::new();
+		for (p, production) in grammar.productions.iter().enumerate() {
+			let (starts, empty) = first_of(&production.rhs, &first, &nullable);
+			let mut lookaheads: BTreeSet<Lookahead> = starts.into_iter().map(Some).collect();
+			if empty { lookaheads.extend(follow[&production.lhs].iter().cloned()); }
+			for lookahead in lookaheads {
+				table.entry((production.lhs.clone(), lookahead)).or_insert(vec![]).push(p);
+			}
+		}
+		Ll1 { nullable, first, follow, table }
+	}
+
+	// the table's cells in a stable order: by nonterminal as defined, then by lookahead
+	pub fn cells (&self, grammar: &Grammar) -> Vec<(&String, &Lookahead, &Vec<usize>)> {
+		let mut cells: Vec<(&String, &Lookahead, &Vec<usize>)> = self.table.iter().map(|(k, v)| (&k.0, &k.1, v)).collect();
+		let order = |name: &String| grammar.nonterminals.iter().position(|n| n == name);
+		cells.sort_by(|a, b| (order(a.0), a.1).cmp(&(order(b.0), b.1)));
+		cells
+	}
+
+	pub fn conflicts (&self, grammar: &Grammar) -> Vec<(&String, &Lookahead, &Vec<usize>)> {
+		self.cells(grammar).into_iter().filter(|c| c.2.len() > 1).collect()
+	}
+
+	// the full analysis as text
+	pub fn report (&self, grammar: &Grammar) -> String {
+		let mut out = String::new();
+		let nullable: Vec<&str> = grammar.nonterminals.iter().filter(|n| self.nullable.contains(*n)).map(|n| &**n).collect();
+		out.push_str(&format!("nullable:{}\n", nullable.iter().map(|n| format!(" {}", n)).collect::<String>()));
+		for nonterminal in &grammar.nonterminals {
+			let shown: Vec<String> = self.first[nonterminal].iter().map(|s| format!("{}", s)).collect();
+			out.push_str(&format!("FIRST({}) = {}\n", nonterminal, shown.join(" ")));
+		}
+		for nonterminal in &grammar.nonterminals {
+			let shown: Vec<String> = self.follow[nonterminal].iter().map(show_lookahead).collect();
+			out.push_str(&format!("FOLLOW({}) = {}\n", nonterminal, shown.join(" ")));
+		}
+		out.push_str("table:\n");
+		for (nonterminal, lookahead, productions) in self.cells(grammar) {
+			for &p in productions {
+				out.push_str(&format!("  {}, {}: {}\n", nonterminal, show_lookahead(lookahead), grammar.productions[p]));
+			}
+		}
+		let conflicts = self.conflicts(grammar);
+		for &(nonterminal, lookahead, productions) in &conflicts {
+			out.push_str(&format!("conflict at {}, {}:\n", nonterminal, show_lookahead(lookahead)));
+			for &p in productions { out.push_str(&format!("  {}\n", grammar.productions[p])); }
+		}
+		if conflicts.is_empty() { out.push_str("LL(1): yes\n"); }
+		else { out.push_str(&format!("LL(1): no, {} conflict cell{}\n", conflicts.len(), if conflicts.len() == 1 { "" } else { "s" })); }
+		out
+	}
+}
+
+// what the predictive parser's stack holds: a symbol still to expand or match, or the point
+// where a production's children are all on the tree stack and can be gathered into its node
+enum Frame {
+	Expect(Symbol),
+	Build(usize)
+}
+
+// PARSE: tokenize 'input' and parse it with the LL(1) table (refused if the table has conflicts)
+pub fn parse (grammar: &Grammar, input: &str) -> Result<Tree, String> {
+	let ll1 = Ll1::build(grammar);
+	if !ll1.conflicts(grammar).is_empty() { return Err("the grammar is not LL(1) (see wls254 ll1 for its conflicts)".to_string()); }
+	let tokens: Vec<Token> = grammar.tokenize(input)?;
+	let mut stack: Vec<Frame> = vec![Frame::Expect(Symbol::Nonterminal(grammar.start.clone()))];
+	let mut trees: Vec<Tree> = vec![];
+	let mut pos = 0;
+	while let Some(frame) = stack.pop() {
+		match frame {
+			Frame::Expect(Symbol::Nonterminal(name)) => {
+				let lookahead: Lookahead = tokens.get(pos).map(|t| t.kind.clone());
+				let p = match ll1.table.get(&(name, lookahead)) { Some(found) => found[0], None => return Err(unexpected(&tokens, pos)) };
+				stack.push(Frame::Build(p));
+				for symbol in grammar.productions[p].rhs.iter().rev() { stack.push(Frame::Expect(symbol.clone())); }
+			},
+			Frame::Expect(terminal) => {
+				if pos >= tokens.len() || tokens[pos].kind != terminal { return Err(unexpected(&tokens, pos)); }
+				trees.push(Tree::Leaf(tokens[pos].clone()));
+				pos += 1;
+			},
+			Frame::Build(p) => {
+				let children = trees.split_off(trees.len() - grammar.productions[p].rhs.len());
+				trees.push(Tree::Node(p, children));
+			}
+		}
+	}
+	if pos < tokens.len() { return Err(unexpected(&tokens, pos)); }
+	Ok(trees.pop().unwrap())
+}
+
+// wls254 ll1 [--grammar FILE]: print the analysis; the exit status is 0 only for an LL(1) grammar
+pub fn ll1_command (args: &[String]) -> i32 {
+	let grammar = match args.len() {
+		0 => Grammar::data(),
+		2 if args[0] == "--grammar" => match Grammar::load(&args[1]) { Ok(g) => g, Err(message) => { eprintln!("{}", message); return 2; } },
+		_ => { eprintln!("usage: wls254 ll1 [--grammar FILE]"); return 2; }
+	};
+	let ll1 = Ll1::build(&grammar);
+	print!("{}", ll1.report(&grammar));
+	if ll1.conflicts(&grammar).is_empty() { 0 } else { 1 }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn the_data_grammar_is_ll1_and_left_recursion_is_not () {
+		let data = Grammar::data();
+		let ll1 = Ll1::build(&data);
+		assert!(ll1.conflicts(&data).is_empty() && ll1.report(&data).ends_with("LL(1): yes\n"));
+		assert_eq!(parse(&data, "{a: 1, {b: @c}}").unwrap(), data.parse_input("{a: 1, {b: @c}}").unwrap());
+		assert_eq!(parse(&data, "{a: 1,}"), Err("unexpected \"}\" at offset 6".to_string()));
+		let left = Grammar::parse("E ::= E '+' T | T\nT = /[0-9]+/\n", "lr.grammar").unwrap();
+		let ll1 = Ll1::build(&left);
+		assert_eq!(ll1.conflicts(&left).len(), 1);
+		assert!(ll1.report(&left).ends_with("conflict at E, T:\n  E ::= E '+' T\n  E ::= T\nLL(1): no, 1 conflict cell\n"));
+	}
+}
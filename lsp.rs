@@ -0,0 +1,263 @@
+// Language server for the data/query input format: LSP (JSON-RPC over stdio) built on the parser
+// Documents are synced incrementally and kept as a Document, so each edit only reparses its lines
+use std::collections::HashMap;
+use std::io;
+use std::io::prelude::*;
+use std::path::PathBuf;
+use super::json::{self, Json};
+use super::document::{Document, Analysis, Symbol, is_name_char, utf16_columns};
+use super::{dialect_flag, usage, Dialect, Limits};
+
+// the symbol (if any) under the cursor
+fn symbol_at (symbols: &[Symbol], line: usize, col: usize) -> Option<&Symbol> {
+	symbols.iter().find(|s| s.line == line && s.start <= col && col <= s.end)
+}
+
+// file:///some/path -> /some/path, with %XX escapes decoded
+fn uri_to_path (uri: &str) -> Option<PathBuf> {
+	if !uri.starts_with("file://") { return None; }
+	let bytes = &uri.as_bytes()["file://".len()..];
+	let mut decoded: Vec<u8> = vec![];
+	let mut i = 0;
+	while i < bytes.len() {
+		if bytes[i] == b'%' && i + 2 < bytes.len() {
+			if let Ok(b) = u8::from_str_radix(&String::from_utf8_lossy(&bytes[i+1..i+3]), 16) {
+				decoded.push(b);
+				i += 3;
+				continue;
+			}
+		}
+		decoded.push(bytes[i]);
+		i += 1;
+	}
+	String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+fn range (line: usize, start: usize, end: usize) -> Json {
+	let position = |col: usize| json::object(vec![("line", json::number(line)), ("character", json::number(col))]);
+	json::object(vec![("start", position(start)), ("end", position(end))])
+}
+
+fn location (uri: &str, symbol: &Symbol) -> Json {
+	json::object(vec![("uri", json::string(uri)), ("range", range(symbol.line, symbol.start, symbol.end))])
+}
+
+// the longest message body taken; a longer one is skipped, not read into memory
+const MAX_MESSAGE: usize = 64 << 20;
+
+// MESSAGES: every message is a Content-Length header, a blank line, and a JSON body
+// None at the end of input, and Err for a message that was read past but cannot be taken
+fn read_message<R: BufRead> (input: &mut R) -> Option<Result<String, &'static str>> {
+	let mut length: Option<usize> = None;
+	loop {
+		let mut header = String::new();
+		if input.read_line(&mut header).ok()? == 0 { return None; }
+		let header = header.trim();
+		if header.is_empty() { break; }
+		let lower = header.to_lowercase();
+		if lower.starts_with("content-length:") { length = header["content-length:".len()..].trim().parse::<usize>().ok(); }
+	}
+	let length = length?;
+	if length > MAX_MESSAGE {
+		let skipped = io::copy(&mut input.by_ref().take(length as u64), &mut io::sink()).ok()?;
+		if skipped < length as u64 { return None; }
+		return Some(Err("message longer than 64 MiB"));
+	}
+	let mut body = vec![0; length];
+	input.read_exact(&mut body).ok()?;
+	Some(String::from_utf8(body).map_err(|_| "message is not UTF-8"))
+}
+
+fn send (message: Json) {
+	let body = message.to_string();
+	let stdout = io::stdout();
+	let mut out = stdout.lock();
+	let _ = write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+	let _ = out.flush();
+}
+
+fn respond (id: Json, result: Json) {
+	send(json::object(vec![("jsonrpc", json::string("2.0")), ("id", id), ("result", result)]));
+}
+
+fn respond_error (id: Json, code: f64, message: &str) {
+	let error = json::object(vec![("code", Json::Number(code)), ("message", json::string(message))]);
+	send(json::object(vec![("jsonrpc", json::string("2.0")), ("id", id), ("error", error)]));
+}
+
+fn publish (uri: &str, analysis: &Analysis) {
+	let diagnostics: Vec<Json> = analysis.problems.iter().map(|p| json::object(vec![
+		("range", range(p.line, p.start, p.end)),
+		("severity", json::number(p.severity)),
+		("source", json::string("wls254")),
+		("message", json::string(&p.message))
+	])).collect();
+	let params = json::object(vec![("uri", json::string(uri)), ("diagnostics", Json::Array(diagnostics))]);
+	send(json::object(vec![
+		("jsonrpc", json::string("2.0")),
+		("method", json::string("textDocument/publishDiagnostics")),
+		("params", params)
+	]));
+}
+
+// an LSP Position as (line, character)
+fn position (position: &Json) -> Option<(usize, usize)> {
+	Some((position.get("line")?.as_usize()?, position.get("character")?.as_usize()?))
+}
+
+// where a request's cursor is: (uri, line, character)
+fn cursor (params: &Json) -> Option<(String, usize, usize)> {
+	let uri = params.get("textDocument")?.get("uri")?.as_str()?.to_string();
+	let (line, character) = position(params.get("position")?)?;
+	Some((uri, line, character))
+}
+
+// DEFINITION: from '@ptr' to the list(s) it names
+fn definition (analysis: &Analysis, uri: &str, line: usize, col: usize) -> Json {
+	let target = match symbol_at(&analysis.pointers, line, col) { Some(s) => s, None => return Json::Null };
+	let found: Vec<Json> = analysis.names.iter().filter(|s| s.name == target.name).map(|s| location(uri, s)).collect();
+	if found.is_empty() { return Json::Null; }
+	Json::Array(found)
+}
+
+// REFERENCES: every pointer to the list under the cursor (the cursor may be on its name or on a pointer)
+fn references (analysis: &Analysis, uri: &str, line: usize, col: usize, declarations: bool) -> Json {
+	let name = match symbol_at(&analysis.names, line, col).or(symbol_at(&analysis.pointers, line, col)) {
+		Some(s) => s.name.clone(),
+		None => return Json::Null
+	};
+	let mut found: Vec<Json> = vec![];
+	if declarations { for s in analysis.names.iter().filter(|s| s.name == name) { found.push(location(uri, s)); } }
+	for s in analysis.pointers.iter().filter(|s| s.name == name) { found.push(location(uri, s)); }
+	Json::Array(found)
+}
+
+// COMPLETION: list names, offered when the word before the cursor starts with '@'
+fn completion (analysis: &Analysis, document: &Document, line: usize, col: usize) -> Json {
+	let raw = match document.line(line) { Some(raw) => raw, None => return Json::Array(vec![]) };
+	let columns = utf16_columns(raw);
+	let chars: Vec<char> = raw.chars().collect();
+	let mut i = columns.iter().position(|&c| c >= col).unwrap_or(chars.len());
+	while i > 0 && is_name_char(chars[i-1]) { i -= 1; }
+	if i == 0 || chars[i-1] != '@' { return Json::Array(vec![]); }
+	let mut names: Vec<String> = analysis.names.iter().map(|s| s.name.clone()).collect();
+	names.extend(analysis.included.iter().map(|i| i.1.clone()));
+	names.sort();
+	names.dedup();
+	// kind 18 is CompletionItemKind.Reference
+	Json::Array(names.iter().map(|n| json::object(vec![("label", json::string(n)), ("kind", json::number(18))])).collect())
+}
+
+// SERVE: answer requests on stdin until "exit"; returns the process exit code
+// wls254 lsp [DIALECT] [LIMITS]: documents are checked in the language the same flags give the
+// assignment's program, so the editor and the command line agree on what is an error
+pub fn serve (args: &[String]) -> i32 {
+	let mut dialect = Dialect::new();
+	let mut limits = Limits::new();
+	let mut args = args.iter().cloned();
+	while let Some(arg) = args.next() {
+		if !dialect_flag(&arg, &mut args, &mut dialect, &mut limits) { usage(); }
+	}
+	let stdin = io::stdin();
+	let mut input = stdin.lock();
+	let mut documents: HashMap<String, Document> = HashMap::new();
+	let mut shutdown = false;
+	loop {
+		let message = match read_message(&mut input) {
+			Some(Ok(m)) => m,
+			Some(Err(problem)) => { respond_error(Json::Null, -32600.0, problem); continue; },
+			None => return 1
+		};
+		let message = match json::parse(&message) {
+			Some(m) => m,
+			None => { respond_error(Json::Null, -32700.0, "parse error"); continue; }
+		};
+		let method = message.get("method").and_then(|m| m.as_str()).unwrap_or("").to_string();
+		let id = message.get("id").cloned();
+		let params = message.get("params").cloned().unwrap_or(Json::Null);
+		match &*method {
+			"initialize" => {
+				let capabilities = json::object(vec![
+					("textDocumentSync", json::number(2)),
+					("definitionProvider", Json::Bool(true)),
+					("referencesProvider", Json::Bool(true)),
+					("completionProvider", json::object(vec![("triggerCharacters", Json::Array(vec![json::string("@")]))]))
+				]);
+				let info = json::object(vec![("name", json::string("wls254"))]);
+				respond(id.unwrap_or(Json::Null), json::object(vec![("capabilities", capabilities), ("serverInfo", info)]));
+			},
+			"textDocument/didOpen" => {
+				let document = params.get("textDocument");
+				let uri = document.and_then(|d| d.get("uri")).and_then(|u| u.as_str()).unwrap_or("").to_string();
+				if let Some(text) = document.and_then(|d| d.get("text")).and_then(|t| t.as_str()) {
+					let document = Document::new(text, uri_to_path(&uri), dialect, limits);
+					publish(&uri, document.analysis());
+					documents.insert(uri, document);
+				}
+			},
+			"textDocument/didChange" => {
+				let uri = params.get("textDocument").and_then(|d| d.get("uri")).and_then(|u| u.as_str()).unwrap_or("").to_string();
+				let changes = params.get("contentChanges").and_then(|c| c.as_array()).cloned().unwrap_or(vec![]);
+				if let Some(document) = documents.get_mut(&uri) {
+					// incremental sync: each change replaces a range, or the whole text if it has none
+					for change in &changes {
+						let text = change.get("text").and_then(|t| t.as_str()).unwrap_or("");
+						match change.get("range").and_then(|r| Some((position(r.get("start")?)?, position(r.get("end")?)?))) {
+							Some((start, end)) => document.apply_change(start, end, text),
+							None => document.set_text(text)
+						}
+					}
+					publish(&uri, document.analysis());
+				}
+			},
+			"textDocument/didClose" => {
+				if let Some(uri) = params.get("textDocument").and_then(|d| d.get("uri")).and_then(|u| u.as_str()) {
+					documents.remove(uri);
+					publish(uri, &Analysis { problems: vec![], names: vec![], pointers: vec![], included: vec![] });
+				}
+			},
+			"textDocument/definition" | "textDocument/references" | "textDocument/completion" => {
+				let id = id.unwrap_or(Json::Null);
+				let (uri, line, col) = match cursor(&params) {
+					Some(c) => c,
+					None => { respond_error(id, -32602.0, "invalid params"); continue; }
+				};
+				let result = match documents.get(&uri) {
+					None => Json::Null,
+					Some(document) => match &*method {
+						"textDocument/definition" => definition(document.analysis(), &uri, line, col),
+						"textDocument/references" => {
+							let declarations = params.get("context").and_then(|c| c.get("includeDeclaration")).and_then(|d| d.as_bool());
+							references(document.analysis(), &uri, line, col, declarations.unwrap_or(false))
+						},
+						_ => completion(document.analysis(), document, line, col)
+					}
+				};
+				respond(id, result);
+			},
+			"shutdown" => { shutdown = true; respond(id.unwrap_or(Json::Null), Json::Null); },
+			"exit" => return if shutdown { 0 } else { 1 },
+			_ => {
+				// requests we don't know get an error; notifications we don't know are ignored
+				if let Some(id) = id { respond_error(id, -32601.0, "method not found"); }
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn messages_past_the_cap_are_skipped_unread () {
+		let header = format!("Content-Length: {}\r\n\r\n", MAX_MESSAGE + 1);
+		let body = io::repeat(b' ').take(MAX_MESSAGE as u64 + 1);
+		let next = "Content-Length: 2\r\n\r\n{}Content-Length: 9\r\n\r\n{}";
+		let mut input = io::BufReader::new(header.as_bytes().chain(body).chain(next.as_bytes()));
+		assert_eq!(read_message(&mut input), Some(Err("message longer than 64 MiB")));
+		assert_eq!(read_message(&mut input), Some(Ok("{}".to_string())));
+		// a body cut short ends the input
+		assert_eq!(read_message(&mut input), None);
+	}
+}
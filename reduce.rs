@@ -0,0 +1,235 @@
+// REDUCE: shrink an input to a small one with the same outcome (delta debugging)
+// The outcome kept is either the same rejection (the diagnostic's message, wherever it ends up) or
+// one query's answer. Whole lines go first, in ever smaller chunks (ddmin); then, inside each line,
+// list items are dropped and nested lists replaced by one of their own items. Those edits follow
+// the LIST/ITEMS structure, so a list never loses its last item and the brackets stay matched.
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use super::{answer_query, check_include, dialect_flag, include_file, parse_query, Dialect, Index, InputState, Limits, Mode};
+
+// what has to stay true of the input while it shrinks
+enum Keep {
+	Rejected(String),
+	Answer(String, String)
+}
+
+// how the program is to read the input: INCLUDE lines are resolved relative to 'dir', as for the
+// input file itself, and the lines are taken in the dialect and within the limits given
+struct Reading {
+	dir: PathBuf,
+	dialect: Dialect,
+	limits: Limits
+}
+
+// how the program takes a whole input: the reason it rejects it, or each query with its answer
+fn outcome (lines: &[String], reading: &Reading) -> Result<Vec<(String, String)>, String> {
+	let mut state = InputState::new(Mode::Buffered);
+	state.dialect = reading.dialect;
+	state.limits = reading.limits;
+	for line in lines {
+		let included = match (state.section, check_include(line.trim())) {
+			(super::Section::Data, Some(Ok(path))) => Some(include_file(&mut state, &reading.dir, &path, &mut vec![]).map_err(|d| d.message)),
+			(super::Section::Data, Some(Err(message))) => Some(Err(message.to_string())),
+			_ => None
+		};
+		match included {
+			Some(result) => result?,
+			None => state.feed(line).map_err(|m| m.to_string())?
+		}
+	}
+	state.ended().map_err(|m| m.to_string())?;
+	let deadline = state.deadline(Instant::now());
+	let index = Index::build(&state.datatree, &state.dialect);
+	let mut answers: Vec<(String, String)> = vec![];
+	for query in &state.queries { answers.push((query.clone(), answer_query(&state.datatree, &index, query, &state.dialect, deadline)?)); }
+	Ok(answers)
+}
+
+fn holds (keep: &Keep, lines: &[String], reading: &Reading) -> bool {
+	match (keep, outcome(lines, reading)) {
+		(Keep::Rejected(want), Err(ref got)) => want == got,
+		(Keep::Answer(query, want), Ok(ref answers)) => answers.iter().any(|a| a.0 == *query && a.1 == *want),
+		_ => false
+	}
+}
+
+// DDMIN: drop chunks of lines while the outcome holds, halving the chunk size when nothing goes
+fn reduce_lines (keep: &Keep, lines: &mut Vec<String>, reading: &Reading) {
+	let mut chunks = 2;
+	while lines.len() > 1 {
+		let size = lines.len().div_ceil(chunks);
+		let mut removed = false;
+		let mut start = 0;
+		while start < lines.len() {
+			let end = (start + size).min(lines.len());
+			let mut fewer = lines[..start].to_vec();
+			fewer.extend_from_slice(&lines[end..]);
+			if holds(keep, &fewer, reading) { *lines = fewer; removed = true; } else { start = end; }
+		}
+		if removed { chunks = (chunks - 1).max(2); }
+		else if size == 1 { break; }
+		else { chunks = (chunks * 2).min(lines.len()); }
+	}
+	if lines.len() == 1 && holds(keep, &[], reading) { lines.clear(); }
+}
+
+// a list in a line: where it starts and ends, and the byte span of each of its items
+type ListSpans = (usize, usize, Vec<(usize, usize)>);
+
+// the lists in a line whose brackets match, outermost first: where each starts and ends,
+// and the byte span of each of its items (the text after the first ':', split at its own commas)
+fn lists (line: &str) -> Vec<ListSpans> {
+	let mut found: Vec<ListSpans> = vec![];
+	let mut open: Vec<usize> = vec![];
+	for (i, c) in line.char_indices() {
+		if c == '{' { open.push(i); }
+		if c != '}' { continue; }
+		let start = match open.pop() { Some(start) => start, None => continue };
+		let colon = match line[start..i].find(':') { Some(at) => start + at, None => continue };
+		let mut items: Vec<(usize, usize)> = vec![];
+		let (mut depth, mut from) = (0, colon + 1);
+		for (j, d) in line[colon+1..i+1].char_indices() {
+			let j = colon + 1 + j;
+			if d == '{' { depth += 1; }
+			if d == '}' && depth > 0 { depth -= 1; continue; }
+			if (d == ',' && depth == 0) || j == i {
+				let text = &line[from..j];
+				let lead = text.find(|c: char| !c.is_whitespace()).unwrap_or(text.len());
+				items.push((from + lead, from + lead + text.trim().len()));
+				from = j + 1;
+			}
+		}
+		found.push((start, i + 1, items));
+	}
+	found.sort_by_key(|a| a.0);
+	found
+}
+
+// smaller versions of one line, one edit each: an item dropped, or a list replaced by one of its list items
+fn edits (line: &str) -> Vec<String> {
+	let mut smaller: Vec<String> = vec![];
+	for (start, end, items) in lists(line) {
+		if items.len() > 1 {
+			for k in 0..items.len() {
+				// an item goes together with the separator after it (or before it, for the last)
+				let (from, to) = if k + 1 < items.len() { (items[k].0, items[k+1].0) } else { (items[k-1].1, items[k].1) };
+				smaller.push(format!("{}{}", &line[..from], &line[to..]));
+			}
+		}
+		for &(from, to) in &items {
+			if line[from..to].starts_with('{') && line[from..to].ends_with('}') {
+				smaller.push(format!("{}{}{}", &line[..start], &line[from..to], &line[end..]));
+			}
+		}
+	}
+	smaller
+}
+
+// shrink every line as far as its edits go, taking the first edit that keeps the outcome each time
+fn reduce_items (keep: &Keep, lines: &mut Vec<String>, reading: &Reading) -> bool {
+	let mut changed = false;
+	for n in 0..lines.len() {
+		'line: loop {
+			for smaller in edits(&lines[n]) {
+				let mut candidate = lines.clone();
+				candidate[n] = smaller;
+				if holds(keep, &candidate, reading) { *lines = candidate; changed = true; continue 'line; }
+			}
+			break;
+		}
+	}
+	changed
+}
+
+fn reduce (keep: &Keep, lines: &mut Vec<String>, reading: &Reading) {
+	loop {
+		reduce_lines(keep, lines, reading);
+		if !reduce_items(keep, lines, reading) { break; }
+	}
+}
+
+// wls254 reduce [DIALECT] [LIMITS] [--query Q [--answer TEXT]] [FILE]
+pub fn reduce_command (args: &[String]) -> i32 {
+	let usage = || { eprintln!("usage: wls254 reduce [DIALECT] [LIMITS] [--query Q [--answer TEXT]] [FILE]"); 2 };
+	let (mut query, mut answer, mut file): (Option<String>, Option<String>, Option<String>) = (None, None, None);
+	let mut dialect = Dialect::new();
+	let mut limits = Limits::new();
+	let mut args = args.iter().cloned();
+	while let Some(arg) = args.next() {
+		match &*arg {
+			"--query" => match args.next() { Some(q) => query = Some(q), None => return usage() },
+			"--answer" => match args.next() { Some(a) => answer = Some(a), None => return usage() },
+			_ if file.is_none() && !arg.starts_with("--") => file = Some(arg),
+			_ => if !dialect_flag(&arg, &mut args, &mut dialect, &mut limits) { return usage(); }
+		}
+	}
+	if answer.is_some() && query.is_none() { return usage(); }
+	// the query is read in the dialect, which may come after it on the command line
+	let query = match query.map(|q| parse_query(q.trim(), &dialect)) {
+		Some((true, q)) => Some(q),
+		Some((false, _)) => return usage(),
+		None => None
+	};
+
+	let mut text = String::new();
+	let read = match file {
+		Some(ref path) => std::fs::File::open(path).and_then(|mut f| f.read_to_string(&mut text)),
+		None => io::stdin().read_to_string(&mut text)
+	};
+	if let Err(e) = read { eprintln!("{}: {}", file.unwrap_or("<stdin>".to_string()), e); return 2; }
+	let mut lines: Vec<String> = text.lines().map(|l| l.to_string()).collect();
+	let dir = file.as_ref().and_then(|f| Path::new(f).parent()).unwrap_or(Path::new("")).to_path_buf();
+	let reading = Reading { dir, dialect, limits };
+
+	// the outcome to keep: the input's own rejection, or what the given query answers now (or should)
+	let keep = match (outcome(&lines, &reading), query) {
+		(Err(message), None) => Keep::Rejected(message),
+		(Ok(_), None) => { eprintln!("the input is accepted; give --query to say which answer to keep"); return 2; },
+		(Err(message), Some(_)) => { eprintln!("the input is rejected ({}), so no query is answered", message); return 2; },
+		(Ok(answers), Some(query)) => match answers.into_iter().find(|a| a.0 == query) {
+			Some((_, now)) => Keep::Answer(query, answer.unwrap_or(now)),
+			None => { eprintln!("the input has no query {}", query); return 2; }
+		}
+	};
+	if !holds(&keep, &lines, &reading) { eprintln!("the input does not give that answer"); return 1; }
+	let before = lines.len();
+	reduce(&keep, &mut lines, &reading);
+	for line in &lines { println!("{}", line); }
+	let kept = match keep { Keep::Rejected(ref message) => message.clone(), Keep::Answer(ref q, ref a) => format!("{} => {}", q, a) };
+	eprintln!("reduced {} lines to {}, keeping: {}", before, lines.len(), kept);
+	0
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn reading (dialect: Dialect, limits: Limits) -> Reading {
+		Reading { dir: PathBuf::new(), dialect, limits }
+	}
+
+	#[test]
+	fn reduces_in_the_dialect_and_limits_given () {
+		let text = ["{a: 1, \"x y\"}", "{b: 2}", "{c: {d: \"x y\"}, 3}", ".", "SEARCH \"x y\"", "SUM", "QUIT"];
+		let mut lines: Vec<String> = text.iter().map(|l| l.to_string()).collect();
+		let mut dialect = Dialect::new();
+		// without --quoted the lines are malformed, so there is no answer to keep
+		assert!(outcome(&lines, &reading(dialect, Limits::new())).is_err());
+		dialect.quoted = true;
+		let quoted = reading(dialect, Limits::new());
+		let keep = Keep::Answer("SEARCH \"x y\"".to_string(), "a,d:c".to_string());
+		assert!(holds(&keep, &lines, &quoted));
+		reduce(&keep, &mut lines, &quoted);
+		assert_eq!(lines, ["{a: \"x y\"}", "{c: {d: \"x y\"}}", ".", "SEARCH \"x y\"", "QUIT"]);
+
+		let mut limits = Limits::new();
+		limits.depth = 1;
+		let mut lines: Vec<String> = text.iter().map(|l| l.to_string()).collect();
+		let shallow = reading(dialect, limits);
+		let keep = Keep::Rejected(outcome(&lines, &shallow).err().unwrap());
+		reduce(&keep, &mut lines, &shallow);
+		assert_eq!(lines, ["{c: {d: \"x y\"}}"]);
+	}
+}
@@ -0,0 +1,30 @@
+// Small deterministic random numbers (xorshift64*), so every generated case can be replayed from its seed
+pub struct Rng {
+	state: u64
+}
+
+impl Rng {
+	pub fn new (seed: u64) -> Rng {
+		// the state must never be zero, and nearby seeds should not start out looking alike
+		let mut rng = Rng { state: (seed ^ 0x9E37_79B9_7F4A_7C15) | 1 };
+		for _ in 0..4 { rng.next(); }
+		rng
+	}
+
+	pub fn next (&mut self) -> u64 {
+		self.state ^= self.state >> 12;
+		self.state ^= self.state << 25;
+		self.state ^= self.state >> 27;
+		self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+	}
+
+	// a number in 0..n (n must not be 0)
+	pub fn below (&mut self, n: usize) -> usize {
+		(self.next() % n as u64) as usize
+	}
+
+	// true with probability p (0 never, 1 always)
+	pub fn chance (&mut self, p: f64) -> bool {
+		((self.next() >> 11) as f64) / ((1u64 << 53) as f64) < p
+	}
+}
@@ -0,0 +1,126 @@
+// STRINGS: which words of an input change classification between the --strings modes
+// Every word in a STRING's place (list names, STRING items, pointer targets, SEARCH terms) that
+// one mode takes as a STRING and another does not, or that unicode mode takes in another form
+// (after NFC), is listed with the modes that take it and why each other mode does not. Then,
+// for each mode, how many lines of the input it accepts.
+use std::io;
+use std::io::prelude::*;
+use super::{check_quoted, parse_list, parse_query, unicode, Dialect, Strings, MAX_DEPTH};
+
+const MODES: [(&str, Strings); 3] = [("legacy", Strings::Legacy), ("strict", Strings::Strict), ("unicode", Strings::Unicode)];
+
+// why 'mode' does not take 's' as a STRING
+fn why_not (mode: Strings, s: &str) -> String {
+	let first = match s.chars().next() { Some(first) => first, None => return "it is empty".to_string() };
+	match mode {
+		Strings::Legacy => {
+			if first.is_ascii_digit() { return "it starts with a digit".to_string(); }
+			let bad = s.chars().find(|&c| !c.is_ascii_digit() && !('A'..='z').contains(&c)).unwrap_or(first);
+			format!("{:?} is outside '0'...'9' and 'A'...'z'", bad)
+		},
+		Strings::Strict => {
+			if !first.is_ascii_alphabetic() { return format!("it starts with {:?}, not an ASCII letter", first); }
+			let bad = s.chars().find(|c| !c.is_ascii_alphanumeric()).unwrap_or(first);
+			format!("{:?} is not an ASCII letter or digit", bad)
+		},
+		Strings::Unicode => {
+			let text = unicode::nfc(s);
+			let first = text.chars().next().unwrap_or(first);
+			if !unicode::is_xid_start(first) { return format!("{:?} cannot start an identifier (not XID_Start)", first); }
+			let bad = text.chars().find(|&c| !unicode::is_xid_continue(c)).unwrap_or(first);
+			format!("{:?} cannot continue an identifier (not XID_Continue)", bad)
+		}
+	}
+}
+
+// how the modes take 'word', or None if they all take it the same way
+fn classify (word: &str) -> Option<String> {
+	let taken: Vec<Option<String>> = MODES.iter().map(|m| m.1.check(word).map(|t| t.into_owned())).collect();
+	let same = taken.iter().all(|t| *t == taken[0]);
+	if same { return None; }
+	let yes: Vec<String> = MODES.iter().zip(&taken).filter_map(|(m, t)| t.as_ref().map(|t| {
+		if t == word { m.0.to_string() } else { format!("{} (as {:?} after NFC)", m.0, t) }
+	})).collect();
+	let no: Vec<String> = MODES.iter().zip(&taken).filter(|p| p.1.is_none()).map(|(m, _)| format!("{}: {}", m.0, why_not(m.1, word))).collect();
+	let mut out = if yes.is_empty() { "a STRING in no mode".to_string() } else { format!("a STRING in {}", yes.join(", ")) };
+	if !no.is_empty() { out.push_str(&format!("; not in {}", no.join("; "))); }
+	Some(out)
+}
+
+// the words of a data line, with the '@' of a pointer taken off (QSTRINGs are skipped)
+fn words (line: &str) -> Vec<&str> {
+	let mut found: Vec<&str> = vec![];
+	let mut at = 0;
+	while at < line.len() {
+		let rest = &line[at..];
+		let c = rest.chars().next().unwrap();
+		if c == '"' {
+			at += check_quoted(rest).map(|q| q.0).unwrap_or(1);
+			continue;
+		}
+		if c.is_whitespace() || "{}:,".contains(c) { at += c.len_utf8(); continue; }
+		let end = rest.find(|c: char| c.is_whitespace() || "{}:,\"".contains(c)).unwrap_or(rest.len());
+		let word = &rest[..end];
+		found.push(word.strip_prefix('@').unwrap_or(word));
+		at += end;
+	}
+	found
+}
+
+// the report for one input, printed as it goes; 'file' names it in each line
+fn report (file: &str, text: &str) {
+	let dialects: Vec<Dialect> = MODES.iter().map(|m| Dialect { strings: m.1, ..Dialect::new() }).collect();
+	let mut accepted = [0; 3];
+	let mut lines = 0;
+	let mut data = true;
+	for (n, line) in text.lines().enumerate() {
+		let line = line.trim();
+		if line == "." { data = false; continue; }
+		if line == "QUIT" || line.starts_with("INCLUDE") || line.is_empty() { continue; }
+		lines += 1;
+		let candidates: Vec<&str> = if data { words(line) } else { line.split_whitespace().skip(1).take(1).collect() };
+		let mut seen: Vec<&str> = vec![];
+		for word in candidates {
+			if seen.contains(&word) { continue; }
+			seen.push(word);
+			if let Some(how) = classify(word) { println!("{}:{}: {}: {}", file, n + 1, word, how); }
+		}
+		for (k, dialect) in dialects.iter().enumerate() {
+			let ok = if data { parse_list(line, dialect, MAX_DEPTH).is_ok() } else { parse_query(line, dialect).0 };
+			if ok { accepted[k] += 1; }
+		}
+	}
+	let counts: Vec<String> = MODES.iter().zip(&accepted).map(|(m, a)| format!("{} {}", m.0, a)).collect();
+	println!("{}: lines accepted of {}: {}", file, lines, counts.join(", "));
+}
+
+// wls254 strings [FILE]...: the report for each file, or for stdin
+pub fn strings_command (args: &[String]) -> i32 {
+	if args.iter().any(|a| a.starts_with("--")) { eprintln!("usage: wls254 strings [FILE]..."); return 2; }
+	if args.is_empty() {
+		let mut text = String::new();
+		if let Err(e) = io::stdin().read_to_string(&mut text) { eprintln!("<stdin>: {}", e); return 2; }
+		report("<stdin>", &text);
+	}
+	for path in args {
+		match std::fs::read_to_string(path) {
+			Ok(text) => report(path, &text),
+			Err(e) => { eprintln!("{}: {}", path, e); return 2; }
+		}
+	}
+	0
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn words_are_classified_by_each_mode () {
+		assert_eq!(words("{a_b: \"x y\", @c1, {n\u{e9}: 2}}"), ["a_b", "c1", "n\u{e9}", "2"]);
+		assert_eq!(classify("abc"), None);
+		assert_eq!(classify("a_b").unwrap(), "a STRING in legacy, unicode; not in strict: '_' is not an ASCII letter or digit");
+		assert_eq!(classify("e\u{301}").unwrap(), "a STRING in unicode (as \"\u{e9}\" after NFC); not in legacy: '\\u{301}' is outside '0'...'9' and 'A'...'z'; strict: '\\u{301}' is not an ASCII letter or digit");
+		assert_eq!(classify("^x").unwrap(), "a STRING in legacy; not in strict: it starts with '^', not an ASCII letter; unicode: '^' cannot start an identifier (not XID_Start)");
+	}
+}
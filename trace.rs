@@ -0,0 +1,200 @@
+// TRACE: what a parse tree says about how the input was derived
+// The leftmost derivation step by step (LIST => '{' NAME ':' ITEMS '}' => ...), and the concrete
+// syntax tree with the production that matched each span, as indented text or as a DOT graph
+use super::grammar::{Grammar, Tree};
+
+// which views of a parse to print
+#[derive(Clone, Copy, PartialEq)]
+pub struct Views {
+	pub derivation: bool,
+	pub cst: bool,
+	pub dot: bool
+}
+
+impl Views {
+	pub fn any (&self) -> bool { self.derivation || self.cst || self.dot }
+}
+
+// LEFTMOST DERIVATION: each sentential form, expanding the leftmost nonterminal every step
+pub fn derivation (grammar: &Grammar, tree: &Tree) -> Vec<String> {
+	let mut form: Vec<&Tree> = vec![tree];
+	let mut steps: Vec<String> = vec![];
+	loop {
+		let shown: Vec<String> = form.iter().map(|t| match **t {
+			Tree::Node(p, _) => grammar.productions[p].lhs.clone(),
+			Tree::Leaf(ref token) => format!("{}", token.kind)
+		}).collect();
+		steps.push(if shown.is_empty() { "%empty".to_string() } else { shown.join(" ") });
+		let at = match form.iter().position(|t| matches!(**t, Tree::Node(..))) { Some(at) => at, None => break };
+		let children: Vec<&Tree> = match *form[at] { Tree::Node(_, ref children) => children.iter().collect(), _ => vec![] };
+		form.splice(at..at+1, children);
+	}
+	steps
+}
+
+// every node of the tree in preorder, with its depth and the byte span it covers; a subtree that
+// derives nothing sits where the token before it ended
+// The walks use explicit stacks, so a tree of any depth can be described
+fn nodes (tree: &Tree) -> Vec<(&Tree, usize, usize, usize)> {
+	let mut order: Vec<(&Tree, usize, Option<usize>)> = vec![];
+	let mut rest: Vec<(&Tree, usize, Option<usize>)> = vec![(tree, 0, None)];
+	while let Some((tree, depth, parent)) = rest.pop() {
+		let at = order.len();
+		order.push((tree, depth, parent));
+		if let Tree::Node(_, ref children) = *tree { rest.extend(children.iter().rev().map(|child| (child, depth + 1, Some(at)))); }
+	}
+	// where the first token that is not empty starts and where the last token ends, in each subtree
+	// (children come after their parent in preorder, so backwards each is done before its parent)
+	let mut first: Vec<Option<usize>> = vec![None; order.len()];
+	let mut last: Vec<Option<usize>> = vec![None; order.len()];
+	for at in (0..order.len()).rev() {
+		if let Tree::Leaf(ref token) = *order[at].0 {
+			if token.end > token.start { first[at] = Some(token.start); }
+			last[at] = Some(token.end);
+		}
+		if let Some(parent) = order[at].2 {
+			if first[at].is_some() { first[parent] = first[at]; }
+			if last[parent].is_none() { last[parent] = last[at]; }
+		}
+	}
+	let mut cursor = 0;
+	let mut out = vec![];
+	for (at, &(tree, depth, _)) in order.iter().enumerate() {
+		out.push((tree, depth, first[at].unwrap_or(cursor), last[at].unwrap_or(cursor)));
+		if let Tree::Leaf(ref token) = *tree { cursor = token.end; }
+	}
+	out
+}
+
+// CST: one line per node, indented by depth: its production (or token), then its span and text
+pub fn cst_text (grammar: &Grammar, tree: &Tree, input: &str) -> String {
+	let mut out = String::new();
+	for (tree, depth, start, end) in nodes(tree) {
+		for _ in 0..depth { out.push_str("  "); }
+		match *tree {
+			Tree::Leaf(ref token) => out.push_str(&format!("{}  {}..{} {:?}\n", token.kind, start, end, token.text)),
+			Tree::Node(p, _) => out.push_str(&format!("{}  {}..{} {:?}\n", grammar.productions[p], start, end, &input[start..end]))
+		}
+	}
+	out
+}
+
+// DOT: the same tree as a graph named 'name'; inner nodes are boxes, tokens plain text
+// Nodes are numbered in preorder, and the edge to each child comes once the child's subtree is written
+pub fn cst_dot (grammar: &Grammar, tree: &Tree, input: &str, name: &str) -> String {
+	let mut out = format!("digraph {} {{\n  ordering=out;\n  node [shape=box, fontname=\"monospace\"];\n", name);
+	// the ids and depths of the nodes from the root down to the last one written
+	let mut open: Vec<(usize, usize)> = vec![];
+	for (id, (tree, depth, start, end)) in nodes(tree).into_iter().enumerate() {
+		close_dot(&mut open, depth, &mut out);
+		match *tree {
+			Tree::Leaf(ref token) => {
+				let label = format!("{}\n{:?}", token.kind, token.text);
+				out.push_str(&format!("  n{} [label=\"{}\", shape=plaintext];\n", id, dot_label(&label)));
+			},
+			Tree::Node(p, _) => {
+				let label = format!("{}\n{}..{} {:?}", grammar.productions[p], start, end, &input[start..end]);
+				out.push_str(&format!("  n{} [label=\"{}\"];\n", id, dot_label(&label)));
+			}
+		}
+		open.push((id, depth));
+	}
+	close_dot(&mut open, 0, &mut out);
+	out.push_str("}\n");
+	out
+}
+
+// the subtrees at 'depth' or deeper are done: write the edges to them from their parents
+fn close_dot (open: &mut Vec<(usize, usize)>, depth: usize, out: &mut String) {
+	while open.last().is_some_and(|o| o.1 >= depth) {
+		let (id, _) = open.pop().unwrap();
+		if let Some(&(parent, _)) = open.last() { out.push_str(&format!("  n{} -> n{};\n", parent, id)); }
+	}
+}
+
+fn dot_label (text: &str) -> String {
+	text.replace("\\", "\\\\").replace("\"", "\\\"").replace("\n", "\\n")
+}
+
+// everything 'views' asks for about one parse; 'name' is the DOT graph's name
+pub fn describe (grammar: &Grammar, tree: &Tree, input: &str, views: Views, name: &str) -> String {
+	let mut out = String::new();
+	if views.derivation {
+		for (n, step) in derivation(grammar, tree).iter().enumerate() {
+			out.push_str(if n == 0 { "   " } else { "=> " });
+			out.push_str(step);
+			out.push('\n');
+		}
+	}
+	if views.cst { out.push_str(&cst_text(grammar, tree, input)); }
+	if views.dot { out.push_str(&cst_dot(grammar, tree, input, name)); }
+	out
+}
+
+// TRACER: each data line of the input, described with the bundled grammar as soon as it is accepted
+// Nothing is kept once a line is described, so tracing does not hold on to the raw text. A line is
+// described before the lines after it are read, so if a later line is bad, the description of the
+// lines before it is already out (on stderr, ahead of the diagnostic)
+pub struct Tracer {
+	grammar: Grammar,
+	views: Views,
+	// how many lines have been described
+	lines: usize
+}
+
+impl Tracer {
+	// 'grammar' is the bundled grammar for the dialect the input is in
+	pub fn new (views: Views, grammar: Grammar) -> Tracer {
+		Tracer { grammar, views, lines: 0 }
+	}
+
+	// a data line check_data accepted
+	pub fn data_line (&mut self, line: &str) {
+		self.lines += 1;
+		let n = self.lines;
+		let header = if self.views.dot { format!("// data line {}: {}\n", n, line) } else { format!("data line {}: {}\n", n, line) };
+		match self.grammar.parse_input(line) {
+			Ok(tree) => eprint!("{}{}", header, describe(&self.grammar, &tree, line, self.views, &format!("line{}", n))),
+			// check_data and the bundled grammar agree (wls254 check-engines), but say so if they ever do not
+			Err(message) => eprintln!("{}{}the bundled grammar rejects this line: {}", header, if self.views.dot { "// " } else { "" }, message)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn derivation_and_cst_of_a_data_line () {
+		let grammar = Grammar::data();
+		let tree = grammar.parse_input("{a: 1}").unwrap();
+		assert_eq!(derivation(&grammar, &tree), ["LIST", "'{' NAME ':' ITEMS '}'", "'{' STRING ':' ITEMS '}'",
+			"'{' STRING ':' ONEITEM MORE '}'", "'{' STRING ':' NUMBER MORE '}'", "'{' STRING ':' NUMBER '}'"]);
+		let cst = cst_text(&grammar, &tree, "{a: 1}");
+		let lines: Vec<&str> = cst.lines().collect();
+		assert_eq!(lines[0], "LIST ::= '{' NAME ':' ITEMS '}'  0..6 \"{a: 1}\"");
+		assert_eq!(lines[5], "  ITEMS ::= ONEITEM MORE  4..5 \"1\"");
+		assert_eq!(lines[8], "    MORE ::= %empty  5..5 \"\"");
+		let dot = cst_dot(&grammar, &tree, "{a: 1}", "line1");
+		assert!(dot.starts_with("digraph line1 {") && dot.contains("  n0 -> n1;\n"));
+	}
+
+	#[test]
+	fn deep_lines_are_described_without_recursion () {
+		let depth = 1000;
+		let mut line = String::new();
+		for n in 0..depth { line.push_str(&format!("{{a{}: ", n)); }
+		line.push('1');
+		for _ in 0..depth { line.push('}'); }
+		let grammar = Grammar::data();
+		let tree = grammar.parse_input(&line).unwrap();
+		let copy = tree.clone();
+		assert!(copy == tree);
+		let cst = cst_text(&grammar, &tree, &line);
+		assert_eq!(cst.lines().next(), Some(&*format!("LIST ::= '{{' NAME ':' ITEMS '}}'  0..{} {:?}", line.len(), line)));
+		let dot = cst_dot(&grammar, &tree, &line, "deep");
+		assert_eq!(dot.matches(" -> ").count() + 1, cst.lines().count());
+		assert!(grammar.render_tree(&tree).ends_with("'}' \"}\"\n"));
+	}
+}
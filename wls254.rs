@@ -1,8 +1,34 @@
 // Written in Rust Beta
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::collections::hash_map::Entry;
 use std::io;
+use std::time::{Duration, Instant};
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 
-enum Type { List, ListString, Number, Pointer }
+mod bench;
+mod bignum;
+mod cyk;
+mod differential;
+mod document;
+mod earley;
+mod export;
+mod generate;
+mod grammar;
+mod hygiene;
+mod json;
+mod lalr;
+mod ll1;
+pub mod lsp;
+mod reduce;
+mod rng;
+mod strings;
+mod trace;
+mod unicode;
+
+// a Pair node's value is its key, and its one child the item the key is given
+enum Type { List, ListString, Number, Pointer, Pair }
 
 impl Clone for Type {
 	fn clone (&self) -> Type {
@@ -10,7 +36,8 @@ impl Clone for Type {
 			Type::List => Type::List,
 			Type::ListString => Type::ListString,
 			Type::Number => Type::Number,
-			Type::Pointer => Type::Pointer
+			Type::Pointer => Type::Pointer,
+			Type::Pair => Type::Pair
 		}
 	}
 }
@@ -21,9 +48,11 @@ struct Node {
 	children: Vec<Node>
 }
 
-impl Clone for Node {
-	fn clone (&self) -> Node {
-		return Node { value: self.value.clone(), nodetype: self.nodetype.clone(), children: self.children.clone() };
+// dropping a tree takes its lists apart one at a time, so no nesting depth can overflow the stack
+impl Drop for Node {
+	fn drop (&mut self) {
+		let mut rest: Vec<Node> = std::mem::take(&mut self.children);
+		while let Some(mut node) = rest.pop() { rest.append(&mut node.children); }
 	}
 }
 
@@ -32,118 +61,389 @@ impl Clone for Node {
 fn check_numeric(s: &str) -> bool { s.parse::<i64>().is_ok() }
 
 // STRING ::= [a-zA-z][0-9a-zA-z]
-// checks if 's' is a STRING
+// checks if 's' is a STRING, as it always has: 'A'...'z' also takes [ \ ] ^ _ and `, so this is
+// really [A-z][0-9A-z]* (--strings legacy; see Strings for the others)
 fn check_string(s: &str) -> bool {
 	if s.len() == 0 { return false; }
 	// ensure that the first char is not a numeric digit
 	match s.chars().nth(0).unwrap() { '0'...'9' => return false, _ => {} }
-	let mut matcher = 0;
-	for token in s.chars() {
-		match token {
-			'0'...'9' => matcher += 1,
-			'a'...'z' => matcher += 1,
-			'A'...'z' => matcher += 1,
-			_ => {}
+	// every char must be one of these (counted in chars, not bytes)
+	return s.chars().all(|token| matches!(token, '0'...'9' | 'a'...'z' | 'A'...'z'));
+}
+
+// STRINGS: which chars a STRING (a list name, a STRING item, a pointer's target) is made of
+// Legacy is check_string; strict is the documented [a-zA-Z][0-9a-zA-Z]* exactly; unicode is an
+// identifier in the Unicode sense (an XID_Start char, then XID_Continue chars) once put in NFC, so
+// the composed and decomposed ways of writing an accented letter are the same STRING.
+#[derive(Clone, Copy, PartialEq)]
+enum Strings { Legacy, Strict, Unicode }
+
+impl Strings {
+	// the STRING 's' stands for (as stored and compared), if it is one
+	fn check<'a> (&self, s: &'a str) -> Option<Cow<'a, str>> {
+		match *self {
+			Strings::Legacy => return if check_string(s) { Some(Cow::Borrowed(s)) } else { None },
+			Strings::Strict => {
+				let mut chars = s.chars();
+				let first = chars.next().map(|c| c.is_ascii_alphabetic()).unwrap_or(false);
+				return if first && chars.all(|c| c.is_ascii_alphanumeric()) { Some(Cow::Borrowed(s)) } else { None };
+			},
+			Strings::Unicode => {
+				let text = unicode::nfc(s);
+				let ok = {
+					let mut chars = text.chars();
+					chars.next().map(unicode::is_xid_start).unwrap_or(false) && chars.all(unicode::is_xid_continue)
+				};
+				return if ok { Some(text) } else { None };
+			}
+		}
+	}
+
+	// the STRING class for the bundled grammar, as a pattern; the unicode one is looser than
+	// the real check, which no pattern here can say
+	fn pattern (&self) -> &'static str {
+		match *self {
+			Strings::Legacy => "[A-z][0-9A-z]*",
+			Strings::Strict => "[a-zA-Z][0-9a-zA-Z]*",
+			Strings::Unicode => "[^\\s0-9{}:,@\"+.-][^\\s{}:,@\"]*"
+		}
+	}
+}
+
+// QSTRING ::= '"' (CHAR | ESCAPE)* '"', for STRINGs with spaces, punctuation or any Unicode in them
+// CHAR ::= any char but '"', '\' and the control chars below U+0020
+// ESCAPE ::= '\' ('"' | '\' | 'n' | 'r' | 't' | 'u{' [0-9a-fA-F]{1,6} '}'), where the u{...}
+// names a Unicode scalar value
+// the length in bytes of the QSTRING 's' starts with, and the text it stands for
+fn check_quoted<'a>(s: &'a str) -> Option<(usize, Cow<'a, str>)> {
+	if !s.starts_with('"') { return None; }
+	let mut text = String::new();
+	let mut escaped = false;
+	let mut chars = s.char_indices().skip(1);
+	while let Some((at, c)) = chars.next() {
+		match c {
+			'"' => {
+				// with no escapes the text is just what is between the quotes
+				if !escaped { return Some((at + 1, Cow::Borrowed(&s[1..at]))); }
+				return Some((at + 1, Cow::Owned(text)));
+			},
+			'\\' => {
+				escaped = true;
+				match chars.next().map(|e| e.1) {
+					Some('"') => text.push('"'),
+					Some('\\') => text.push('\\'),
+					Some('n') => text.push('\n'),
+					Some('r') => text.push('\r'),
+					Some('t') => text.push('\t'),
+					Some('u') => {
+						if chars.next().map(|e| e.1) != Some('{') { return None; }
+						let mut hex = String::new();
+						loop {
+							match chars.next().map(|e| e.1) {
+								Some('}') => break,
+								Some(h) if h.is_ascii_hexdigit() && hex.len() < 6 => hex.push(h),
+								_ => return None
+							}
+						}
+						text.push(u32::from_str_radix(&hex, 16).ok().and_then(std::char::from_u32)?);
+					},
+					_ => return None
+				}
+			},
+			c if (c as u32) < 0x20 => return None,
+			c => text.push(c)
+		}
+	}
+	return None;
+}
+
+// DIALECT: extensions of the assignment's language, each one off unless it is asked for
+// With big arithmetic a NUMBER may be any size and SUM is exact however large it gets; with
+// checked arithmetic (the default) every NUMBER and every SUM must lie in the 64-bit integer range,
+// and a SUM that does not is rejected with a diagnostic. The notation says how else a NUMBER may be
+// written (decimals, exponents, hex, digit separators); SUM adds whatever is written exactly.
+// A STRING written as a QSTRING is the text it stands for: "abc" and abc are the same STRING.
+// With keys, an item may be KEY=ONEITEM, giving the item a name inside its list.
+#[derive(Clone, Copy, PartialEq)]
+enum Arithmetic { Checked, Big }
+
+#[derive(Clone, Copy)]
+struct Dialect {
+	arithmetic: Arithmetic,
+	numbers: bignum::Notation,
+	// STRING items (and SEARCH terms) may also be written as QSTRINGs
+	quoted: bool,
+	strings: Strings,
+	// items may be KEY=ONEITEM pairs, and GET looks them up
+	keys: bool
+}
+
+impl Dialect {
+	// the assignment's language, as check_data and check_query take it
+	fn new () -> Dialect {
+		return Dialect { arithmetic: Arithmetic::Checked, numbers: bignum::Notation::plain(), quoted: false, strings: Strings::Legacy, keys: false };
+	}
+
+	fn check_numeric (&self, s: &str) -> bool {
+		if check_numeric(s) { return true; }
+		match bignum::Decimal::parse(s, &self.numbers) {
+			Some(number) => return self.arithmetic == Arithmetic::Big || number.within_i64(),
+			None => return false
+		}
+	}
+
+	// the text a QSTRING stands for, in NFC if STRINGs are compared that way
+	fn text<'a> (&self, text: Cow<'a, str>) -> Cow<'a, str> {
+		if self.strings != Strings::Unicode { return text; }
+		match unicode::nfc(&text) {
+			Cow::Borrowed(_) => return text,
+			Cow::Owned(normal) => return Cow::Owned(normal)
+		}
+	}
+
+	// the bundled grammar for this dialect, as --trace describes lines with
+	fn grammar (&self) -> grammar::Grammar {
+		return grammar::Grammar::data_dialect(&self.numbers.pattern(), self.strings.pattern(), self.quoted, self.keys);
+	}
+
+	// the text a NUMBER is found by: as written, unless the notation lets one value be written
+	// more than one way, when 1.0, 1e0, +1 and 0x1 must all find the 1
+	fn number_key<'a> (&self, s: &'a str) -> Cow<'a, str> {
+		if !self.numbers.extended() { return Cow::Borrowed(s); }
+		match bignum::Decimal::parse(s, &self.numbers) {
+			Some(number) => return Cow::Owned(number.key()),
+			None => return Cow::Borrowed(s)
 		}
 	}
-	matcher == s.len()
 }
 
 // PTR :: = '@' STRING
-// checks if 's' is a PTR
-fn check_pointer(s: &str) -> bool {
-	if s.starts_with("@") { 
-		let mut newsome = String::new();
-		for n in 1..s.len() { newsome.push ( s.chars().nth(n).unwrap() ); }
-		return check_string(&*newsome); 
-	} else { return false; }
-}
-
-// ONEITEM: check if it's one of four possible items
-// ONEITEM ::= NUMBER | STRING | PTR | LIST
-fn check_oneitem(s: &str) -> (bool, Node) {
-	let mut thenode;
-	let mut flag = true;
-	if check_numeric(s) { 
-		thenode = Node { value: s.to_string(), nodetype: Type::Number, children: vec![] };
-	} else if check_string(s) {
-		thenode = Node { value: s.to_string(), nodetype: Type::ListString, children: vec![] };
-	} else if check_pointer(s) {
-		// NOTE: NO NEED TO STORE THE "@"" FOR POINTERS -- get rid of it first
-		let mut newsome = String::new();
-		for n in 1..s.len() { newsome.push ( s.chars().nth(n).unwrap() ); }
-		thenode = Node { value: newsome, nodetype: Type::Pointer, children: vec![] };
-	} else { let tuple = check_data(s); flag = tuple.0; thenode = tuple.1; }
-	return (flag, thenode);
-}
-
-// ITEMS ::= ONEITEM | ONEITEM ',' ITEMS
-// Be sure to deal with whitespace!
-fn check_items(s: &str) -> (bool,Vec<Node>) {
-	let mut children: Vec<Node> = vec![];
-	if s.len() == 0 { return (false, children); }
-	let matchalist: Vec<char> = s.chars().collect();
-	let mut sss = "".to_string();
-	let mut workinglist: Vec<String> = vec![];
-	let mut innerlist = false;
-	for c in matchalist {
-		if innerlist { sss.push(c); if c == '}' { innerlist = false; } }
-		else {
-			if c == '{' { innerlist = true; }
-			if c != ',' { sss.push(c); }
-			else { workinglist.push( sss.trim().to_string()); sss = "".to_string(); }
+// checks if 's' is a PTR, and gives the name it points to
+fn check_pointer<'a>(s: &'a str, strings: Strings) -> Option<Cow<'a, str>> {
+	if !s.starts_with("@") { return None; }
+	return strings.check(&s[1..]);
+}
+
+// DATA LINES: a LIST is parsed in a single pass into a tree that borrows every name and leaf
+// from the line itself, so only the item lists allocate. The owned Node tree the queries run on
+// is made from it once the line is known to be good (the raw input is not kept around).
+// Nothing here recurses per nesting level: the lists still open are kept on an explicit stack,
+// and how deep they may go is capped so a bad line fails with a diagnostic, not a crash.
+// (a QSTRING's text is only allocated when it has escapes in it)
+// A Pair is a KEY '=' ONEITEM item: the key and the item it is given.
+enum Item<'a> { Number(&'a str), Str(Cow<'a, str>), Pointer(Cow<'a, str>), List(List<'a>), Pair(Cow<'a, str>, Box<Item<'a>>) }
+
+struct List<'a> {
+	name: Cow<'a, str>,
+	items: Vec<Item<'a>>
+}
+
+// lists may nest this deep unless --max-depth says otherwise
+const MAX_DEPTH: usize = 1000;
+
+const MALFORMED: &str = "malformed data line";
+const TOO_DEEP: &str = "lists nested deeper than --max-depth allows";
+
+// a Pair node for 'key' holding 'value'
+fn pair_node (key: Option<&str>, value: Node) -> Node {
+	match key {
+		Some(key) => return Node { value: key.to_string(), nodetype: Type::Pair, children: vec![value] },
+		None => return value
+	}
+}
+
+impl<'a> List<'a> {
+	fn to_node (&self) -> Node {
+		// the lists being made, each with the key it is given (if any) and the children made so far
+		let mut open: Vec<(&List, Option<&str>, Vec<Node>)> = vec![(self, None, vec![])];
+		loop {
+			let next = { let top = open.last().unwrap(); top.0.items.get(top.2.len()) };
+			let (key, next) = match next {
+				Some(Item::Pair(key, value)) => (Some(&**key), Some(&**value)),
+				next => (None, next)
+			};
+			let leaf = match next {
+				Some(Item::List(inner)) => { open.push((inner, key, vec![])); continue; },
+				Some(&Item::Number(text)) => Node { value: text.to_string(), nodetype: Type::Number, children: vec![] },
+				Some(Item::Str(text)) => Node { value: text.to_string(), nodetype: Type::ListString, children: vec![] },
+				// NOTE: NO NEED TO STORE THE "@" FOR POINTERS -- it is not part of the slice
+				Some(Item::Pointer(name)) => Node { value: name.to_string(), nodetype: Type::Pointer, children: vec![] },
+				// the parser never gives a pair a pair
+				Some(&Item::Pair(..)) => unreachable!(),
+				None => {
+					let (list, key, children) = open.pop().unwrap();
+					let node = pair_node(key, Node { value: list.name.to_string(), nodetype: Type::List, children });
+					if open.is_empty() { return node; }
+					open.last_mut().unwrap().2.push(node);
+					continue;
+				}
+			};
+			open.last_mut().unwrap().2.push(pair_node(key, leaf));
+		}
+	}
+}
+
+impl<'a> Item<'a> {
+	// whether the item is a LIST, or a pair giving a key a LIST
+	fn is_list (&self) -> bool {
+		match *self {
+			Item::List(_) => return true,
+			Item::Pair(_, ref value) => return value.is_list(),
+			_ => return false
 		}
 	}
-	let lastone = sss.trim().to_string();
-	if lastone == "".to_string() { return (false, children); }
-	workinglist.push(lastone);
-	if workinglist.len() == 0 { return(false,children); }
-	for elem in workinglist {
-		let duple = check_oneitem(&*elem);
-		if duple.0 { children.push(duple.1); } else { return(false, children); }
+}
+
+impl<'a> List<'a> {
+	// how many nodes the list makes: itself and everything in it
+	fn size (&self) -> usize {
+		let mut size = 0;
+		let mut rest: Vec<&List> = vec![self];
+		while let Some(list) = rest.pop() {
+			size += 1 + list.items.len();
+			for item in &list.items {
+				// a pair is a node for the key and one for the item
+				let item = match *item { Item::Pair(_, ref value) => { size += 1; &**value }, ref item => item };
+				if let Item::List(ref inner) = *item { size -= 1; rest.push(inner); }
+			}
+		}
+		return size;
 	}
-	return (true, children);
+}
+
+impl<'a> Drop for List<'a> {
+	fn drop (&mut self) {
+		let mut rest: Vec<Item> = self.items.drain(..).collect();
+		while let Some(item) = rest.pop() {
+			match item {
+				Item::List(mut list) => rest.append(&mut list.items),
+				Item::Pair(_, value) => rest.push(*value),
+				_ => {}
+			}
+		}
+	}
+}
+
+// where the parser is in the line
+struct Cursor<'a> {
+	text: &'a str,
+	at: usize,
+	dialect: Dialect
+}
+
+impl<'a> Cursor<'a> {
+	fn peek (&self) -> Option<char> { self.text[self.at..].chars().next() }
+
+	fn eat (&mut self, c: char) -> bool {
+		if self.peek() != Some(c) { return false; }
+		self.at += c.len_utf8();
+		return true;
+	}
+
+	// whitespace is allowed between any two tokens
+	fn skip_space (&mut self) {
+		while let Some(c) = self.peek() { if !c.is_whitespace() { break; } self.at += c.len_utf8(); }
+	}
+
+	// the run of characters up to whitespace or one of 'stops'
+	fn word (&mut self, stops: &[char]) -> &'a str {
+		let start = self.at;
+		while let Some(c) = self.peek() { if c.is_whitespace() || stops.contains(&c) { break; } self.at += c.len_utf8(); }
+		return &self.text[start..self.at];
+	}
+
+	// LIST ::= '{' NAME ':' ITEMS '}'
+	// ITEMS ::= ONEITEM | ONEITEM ',' ITEMS
+	// (data.grammar, and so --trace, writes this left-factored: ITEMS ::= ONEITEM MORE, MORE ::= %empty | ',' ITEMS)
+	// with --keys, any item may be a PAIR instead: PAIR ::= KEY '=' ONEITEM, KEY ::= STRING
+	// A list that is itself an item may have a LIST only as its last item, as check_items always
+	// allowed: it kept a nested list's commas together only up to the first '}', so an item after
+	// a list inside a nested list was split off from it and the line rejected (data.grammar says
+	// this with INNER and LAST).
+	fn list (&mut self, max_depth: usize) -> Result<List<'a>, &'static str> {
+		// the lists opened and not yet closed, innermost last, each with the key it is given (if any)
+		let mut open: Vec<(Option<Cow<'a, str>>, List<'a>)> = vec![];
+		let mut key: Option<Cow<'a, str>> = None;
+		loop {
+			// at a '{': the list's NAME and ':'
+			if !self.eat('{') { return Err(MALFORMED); }
+			if open.len() == max_depth { return Err(TOO_DEEP); }
+			self.skip_space();
+			let name = self.word(&[':']);
+			let name = self.dialect.strings.check(name).ok_or(MALFORMED)?;
+			self.skip_space();
+			if !self.eat(':') { return Err(MALFORMED); }
+			open.push((key.take(), List { name, items: vec![] }));
+			// then ONEITEMs until one is a LIST, which is opened next time round
+			loop {
+				self.skip_space();
+				if self.dialect.keys { key = self.key()?; }
+				if self.peek() == Some('{') { break; }
+				let item = self.leaf().ok_or(MALFORMED)?;
+				let item = match key.take() { Some(key) => Item::Pair(key, Box::new(item)), None => item };
+				open.last_mut().unwrap().1.items.push(item);
+				// after an item: ',' for another, or '}' closing lists until the next ','
+				loop {
+					self.skip_space();
+					if self.eat(',') {
+						if open.len() > 1 && open.last().unwrap().1.items.last().is_some_and(Item::is_list) { return Err(MALFORMED); }
+						break;
+					}
+					if !self.eat('}') { return Err(MALFORMED); }
+					let (given, done) = open.pop().unwrap();
+					let done = match given { Some(given) => Item::Pair(given, Box::new(Item::List(done))), None => Item::List(done) };
+					match (open.last_mut(), done) {
+						(Some(outer), done) => outer.1.items.push(done),
+						(None, Item::List(done)) => return Ok(done),
+						(None, _) => unreachable!()
+					}
+				}
+			}
+		}
+	}
+
+	// a KEY and its '=', if the next item starts with them (the cursor is left after the '=', and
+	// at the first non-space char after it), or None with the cursor where it was
+	fn key (&mut self) -> Result<Option<Cow<'a, str>>, &'static str> {
+		let start = self.at;
+		let word = self.word(&[',', '{', '}', '=']);
+		self.skip_space();
+		if !self.eat('=') { self.at = start; return Ok(None); }
+		let key = self.dialect.strings.check(word).ok_or(MALFORMED)?;
+		self.skip_space();
+		return Ok(Some(key));
+	}
+
+	// ONEITEM ::= NUMBER | STRING | PTR (a LIST is handled by list()), or a QSTRING if the dialect has them
+	fn leaf (&mut self) -> Option<Item<'a>> {
+		if self.dialect.quoted && self.peek() == Some('"') {
+			let (length, text) = check_quoted(&self.text[self.at..])?;
+			self.at += length;
+			return Some(Item::Str(self.dialect.text(text)));
+		}
+		let word = self.word(&[',', '{', '}']);
+		if self.dialect.check_numeric(word) { return Some(Item::Number(word)); }
+		if let Some(text) = self.dialect.strings.check(word) { return Some(Item::Str(text)); }
+		if let Some(name) = check_pointer(word, self.dialect.strings) { return Some(Item::Pointer(name)); }
+		return None;
+	}
+}
+
+// a whole data line as a LIST: nothing may come before the '{' or after the '}'
+// (whitespace at front and end of the line is already trimmed at this point)
+fn parse_list<'a>(s: &'a str, dialect: &Dialect, max_depth: usize) -> Result<List<'a>, &'static str> {
+	let mut cursor = Cursor { text: s, at: 0, dialect: *dialect };
+	let list = cursor.list(max_depth)?;
+	if cursor.at != s.len() { return Err(MALFORMED); }
+	return Ok(list);
 }
 
 // LIST: check if valid list and return the node (each line can only have a single tree)
-// Note: Allow for whitespace between tokens
-// Whitespace at front and end of string is already trimmed at this point
 fn check_data(s: &str) -> (bool, Node) {
-	// Convert slice to a String so we can do String operations
-	let ss = s.to_string();
-	// Set default node and default flag
-	let mut thenode = Node { value: "ERROR".to_string(), nodetype: Type::List, children: vec![] };
-	let mut flag = true;
-	// If length is not long enough to satisfy basic conditions, fail
-	if ss.len() < 5 { return(false,thenode); }
-	// Check { (first char)
-	if !ss.starts_with('{') { return(false,thenode); }
-	// Check } (last char)
-	if !ss.ends_with('}') { return(false,thenode); }
-	// Break up the String by the first instance of ':'
-	let matchalist: Vec<char> = s.chars().collect();
-	let mut num = matchalist.len();
-	for n in 0..matchalist.len() { if matchalist[n] == ':' { num = n; break; } }
-	let mut lhs = "".to_string();
-	let mut rhs = "".to_string();
-	// If no ':' was found, or it's found right before the '{', the string is invalid
-	if num > matchalist.len()-2 { return(false,thenode); }
-	else {
-		for j in 1..num { lhs.push(matchalist[j]); }
-		for j in num+1..matchalist.len()-1 { rhs.push(matchalist[j]); }
-	}
-	// If we get to here, the string is valid, and we have both parts broken up
-	// We will trim the leading and trailing whitespace
-	let namefield = lhs.trim();
-	let childrenfield = rhs.trim();
-	// NAME: Check NAME (everything between "{ :") using check_string()
-	if !check_string(namefield) { return(false,thenode); }
-	thenode.value = namefield.to_string();
-	// Check ITEMS (everything between ": }") using check_items())
-	let itemtuple = check_items(childrenfield);
-	if !itemtuple.0 { flag = false; }
-	thenode.children = itemtuple.1;
-	return (flag, thenode);
+	match parse_list(s, &Dialect::new(), MAX_DEPTH) {
+		Ok(list) => return (true, list.to_node()),
+		Err(_) => return (false, Node { value: "ERROR".to_string(), nodetype: Type::List, children: vec![] })
+	}
 }
 
 // ONEQ: check if valid query and return the query name
@@ -151,21 +451,42 @@ fn check_data(s: &str) -> (bool, Node) {
 // Assumes that the leading and trailing whitespace has already been removed
 // We can still expect tabs/spaces after 'search'
 fn check_query(s: &str) -> (bool, String) {
+	return parse_query(s, &Dialect::new());
+}
+
+// a query in any dialect (SEARCH takes whatever NUMBERs the data lines can have)
+fn parse_query(s: &str, dialect: &Dialect) -> (bool, String) {
 	if s == "SUM" || s == "sum" {
 		return (true, "SUM".to_string());
 	} else if s == "PTRS" || s == "ptrs" {
 		return (true, "PTRS".to_string());
 	} else if s == "NAMECHECK" || s == "namecheck" {
 		return (true, "NAMECHECK".to_string());
+	} else if dialect.keys && (s.starts_with("GET") || s.starts_with("get")) && s[3..].starts_with(char::is_whitespace) {
+		// GET NAME.KEY, with no space around the '.'
+		let term = s[3..].trim();
+		let at = match term.find('.') { Some(at) => at, None => return (false, "FAIL".to_string()) };
+		match (dialect.strings.check(&term[..at]), dialect.strings.check(&term[at+1..])) {
+			(Some(name), Some(key)) => return (true, format!("GET {}.{}", name, key)),
+			_ => return (false, "FAIL".to_string())
+		}
 	} else {
 		// either a search query, or just junk
+		if dialect.quoted && (s.starts_with("search") || s.starts_with("SEARCH")) {
+			let term = s[6..].trim();
+			if s[6..].starts_with(char::is_whitespace) && term.starts_with('"') {
+				// a quoted term is the whole rest of the line, with nothing after its closing quote
+				if check_quoted(term).map(|q| q.0) == Some(term.len()) { return (true, "SEARCH ".to_string() + term); }
+				return (false, "FAIL".to_string());
+			}
+		}
 		let talker = s.replace("\t"," ");
 		let mut squery: Vec<&str> = talker.split(char::is_whitespace).collect();
 		squery.retain( |&x| x != "");
 		// note: rust does not have logical short-circuit evaluation
 		if squery.len() != 2 { return (false, "FAIL".to_string()); }
-		if squery[0] == "search" || squery[0] == "SEARCH" 
-		&& check_string(squery[1].clone()) || check_numeric(squery[1].clone()) {
+		if (squery[0] == "search" || squery[0] == "SEARCH")
+		&& (dialect.strings.check(squery[1]).is_some() || dialect.check_numeric(squery[1])) {
 				let qsearch = "SEARCH ".to_string() + squery[1];
 				return (true, qsearch);
 		}
@@ -173,196 +494,859 @@ fn check_query(s: &str) -> (bool, String) {
 	}
 }
 
-// INPUT: Build the parse tree for data and list what queries need to be run
-// Also, check that the form of the input is valid, and return a bool telling whether it is
-fn validate_input (input: Vec<String>) -> (Vec<Node>, Vec<String>, bool) {
-	let mut datatree: Vec<Node> = vec![];
-	let mut queries: Vec<String> = vec![];
-	let mut valid = false;
-	// input is too short -- error
-	if input.len() < 2 { return (datatree, queries, false); }
-	// QUIT: no "QUIT" detected -- error
-	if input[input.len()-1].trim() != "QUIT" { return (datatree, queries, false); }
-	// keep track of the line number that we are on
-	let mut lineno = 0;
-	// DATA: goes through the data portion of the input
-	while lineno < input.len()-1 {
-		let line = input[lineno].trim();
-		if line == "." { lineno += 1; valid = true; break; }
-		let duple = check_data(&*line);
-		if !duple.0 { return(datatree, queries, false); }
-		datatree.push(duple.1);
-		lineno += 1;
-	}
-	// no "." detected -- error
-	if !valid { return (datatree, queries, false); }
-	// QUERY: goes through the query portion of the input
-	while lineno < input.len()-1 {
-		let line = input[lineno].trim();
-		let duple = check_query(&*line);
-		if !duple.0 { return (datatree, queries, false); }
-		queries.push(duple.1);
-		lineno += 1;
-	}
-	return (datatree, queries, valid);
-}
-
-// SUM: use recursive descent to go through all nodes and get the sum of numeric fields
-fn recursive_sum (data: Vec<Node>) -> i64 {
-	let mut sum: i64 = 0;
-	for node in data {
-		match node.nodetype  {
-			Type::List => sum += recursive_sum(node.children),
-			Type::Number => sum += node.value.parse::<i64>().unwrap(),
-			_ => {}
-		}
+// MODE: when query answers are released
+// Buffered is the all-or-nothing contract: nothing is printed until QUIT has been seen,
+// so a bad line anywhere produces only ERR. Streaming answers each query as soon as it is read,
+// so output printed before a bad line stays printed and ERR just ends the run.
+#[derive(Clone, Copy, PartialEq)]
+enum Mode { Buffered, Streaming }
+
+// which part of the input the next line belongs to
+#[derive(Clone, Copy, PartialEq)]
+enum Section { Data, Queries, Done }
+
+// DIAGNOSTIC: why an input was rejected, and which file and line it came from
+// line 0 means the problem is with the file as a whole (e.g. it ended too early)
+// includes lists the INCLUDE lines that led to the file, innermost first
+struct Diagnostic {
+	file: String,
+	line: usize,
+	message: String,
+	includes: Vec<(String, usize)>
+}
+
+impl Diagnostic {
+	fn new (file: &str, line: usize, message: &str) -> Diagnostic {
+		return Diagnostic { file: file.to_string(), line, message: message.to_string(), includes: vec![] };
 	}
-	return sum;
 }
 
-// gather all of the names of lists in the tree
-fn recursive_namecheck (data: Vec<Node>) -> Vec<String> {
-	let mut names: Vec<String> = vec![];
-	for node in data {
-		match node.nodetype {
-			Type::List => { 
-				names.push(node.value); 
-				for childstring in recursive_namecheck(node.children) {
-					names.push(childstring);
-				}
+impl std::fmt::Display for Diagnostic {
+	fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		if self.line == 0 { write!(f, "{}: {}", self.file, self.message)?; }
+		else { write!(f, "{}:{}: {}", self.file, self.line, self.message)?; }
+		for &(ref file, line) in &self.includes { write!(f, "\n\tincluded from {}:{}", file, line)?; }
+		return Ok(());
+	}
+}
+
+// LIMITS: caps on what an input can make the program do, for input from people we do not know
+// Each one that is exceeded rejects the input with its own diagnostic, before the memory or time
+// is spent: line length and total size are checked as bytes arrive, node counts before a line's
+// tree is built, and query time while answering.
+#[derive(Clone, Copy)]
+struct Limits {
+	bytes: u64,
+	line: usize,
+	depth: usize,
+	nodes: usize,
+	queries: usize,
+	time: Duration
+}
+
+impl Limits {
+	fn new () -> Limits {
+		return Limits { bytes: 256 << 20, line: 64 << 20, depth: MAX_DEPTH, nodes: 10_000_000, queries: 1_000_000, time: Duration::from_secs(60) };
+	}
+}
+
+const TOO_BIG: &str = "input larger than --max-bytes allows";
+const TOO_LONG: &str = "line longer than --max-line allows";
+const TOO_MANY_NODES: &str = "more data than --max-nodes allows";
+const TOO_MANY_QUERIES: &str = "more queries than --max-queries allows";
+const TOO_SLOW: &str = "queries took longer than --max-time allows";
+
+// INPUT: Build the parse tree for data and list what queries need to be run, one line at a time
+// Only the tree (and, in buffered mode, the validated queries) is kept -- never the raw text
+struct InputState {
+	mode: Mode,
+	datatree: Vec<Node>,
+	queries: Vec<String>,
+	section: Section,
+	// streaming mode only: the index the queries are answered from
+	index: Option<Index>,
+	dialect: Dialect,
+	limits: Limits,
+	// what the input has used of them so far
+	bytes: u64,
+	nodes: usize,
+	query_count: usize,
+	query_time: Duration,
+	tracer: Option<trace::Tracer>
+}
+
+impl InputState {
+	fn new (mode: Mode) -> InputState {
+		return InputState { mode, datatree: vec![], queries: vec![], section: Section::Data, index: None, dialect: Dialect::new(),
+			limits: Limits::new(), bytes: 0, nodes: 0, query_count: 0, query_time: Duration::from_secs(0), tracer: None };
+	}
+
+	// DATA: a line in the data section must be a LIST
+	fn add_data (&mut self, line: &str) -> Result<(), &'static str> {
+		let list = parse_list(line, &self.dialect, self.limits.depth)?;
+		self.nodes += list.size();
+		if self.nodes > self.limits.nodes { return Err(TOO_MANY_NODES); }
+		self.datatree.push(list.to_node());
+		if let Some(ref mut tracer) = self.tracer { tracer.data_line(line); }
+		return Ok(());
+	}
+
+	// QUERY: a line in the query section must be a ONEQ
+	fn add_query (&mut self, line: &str) -> Result<(), &'static str> {
+		let duple = parse_query(line, &self.dialect);
+		if !duple.0 { return Err("malformed query"); }
+		self.query_count += 1;
+		if self.query_count > self.limits.queries { return Err(TOO_MANY_QUERIES); }
+		// buffered queries wait for the end of input; streaming ones are answered and forgotten
+		if self.mode == Mode::Buffered { self.queries.push(duple.1); return Ok(()); }
+		let start = Instant::now();
+		let deadline = self.deadline(start);
+		// the data is complete once the first query arrives
+		if self.index.is_none() { self.index = Some(Index::build(&self.datatree, &self.dialect)); }
+		let answer = answer_query(&self.datatree, self.index.as_ref().unwrap(), &duple.1, &self.dialect, deadline);
+		self.query_time += start.elapsed();
+		println!("{}", answer?);
+		return Ok(());
+	}
+
+	// when queries started at 'start' must be done by, given the time they have used already
+	fn deadline (&self, start: Instant) -> Option<Instant> {
+		if self.query_time >= self.limits.time { return Some(start); }
+		return start.checked_add(self.limits.time - self.query_time);
+	}
+
+	// check one line of stdin against the section it falls in, moving on at "." and "QUIT"
+	// fails as soon as the input is known to be invalid
+	fn feed (&mut self, raw: &str) -> Result<(), &'static str> {
+		let line = raw.trim();
+		match self.section {
+			Section::Data => {
+				if line == "." { self.section = Section::Queries; return Ok(()); }
+				return self.add_data(line);
 			},
-			_ => {}
+			Section::Queries => {
+				if line == "QUIT" { self.section = Section::Done; return Ok(()); }
+				return self.add_query(line);
+			},
+			// QUIT has to be the very last line
+			Section::Done => return Err("input continues after QUIT")
 		}
 	}
-	return names;
+
+	// the input is valid only if it ended right after "QUIT"
+	fn ended (&self) -> Result<(), &'static str> {
+		match self.section {
+			Section::Data => return Err("input ended before the '.' separator"),
+			Section::Queries => return Err("input ended before QUIT"),
+			Section::Done => return Ok(())
+		}
+	}
+
+	// called at the end of input
+	// buffered queries are answered here, once the whole input is known to be good
+	fn finish (self) -> Result<(), &'static str> {
+		self.ended()?;
+		if self.mode == Mode::Buffered {
+			// every answer is worked out before any is printed, so running out of time still prints only ERR
+			let deadline = self.deadline(Instant::now());
+			let index = Index::build(&self.datatree, &self.dialect);
+			let mut answers: Vec<String> = vec![];
+			for query in &self.queries { answers.push(answer_query(&self.datatree, &index, query, &self.dialect, deadline)?); }
+			for answer in answers { println!("{}", answer); }
+		}
+		return Ok(());
+	}
+}
+
+// INCLUDE ::= 'INCLUDE' '"' PATH '"'
+// returns None if 's' is not an include line at all, and Some(Err) if it is a malformed one
+fn check_include(s: &str) -> Option<Result<String, &'static str>> {
+	if !s.starts_with("INCLUDE") { return None; }
+	let rest = s["INCLUDE".len()..].trim();
+	if rest.len() < 2 || !rest.starts_with('"') || !rest.ends_with('"') { return Some(Err("malformed INCLUDE")); }
+	let path = &rest[1..rest.len()-1];
+	if path.is_empty() || path.contains('"') { return Some(Err("malformed INCLUDE")); }
+	return Some(Ok(path.to_string()));
+}
+
+// LINES: the next line from 'reader' without its line ending, or None at the end of it
+// Only up to the longest line allowed is read, so an endless line cannot fill memory first
+fn next_line<R: BufRead> (state: &mut InputState, reader: &mut R) -> Result<Option<String>, &'static str> {
+	let mut bytes: Vec<u8> = vec![];
+	// room for the longest line allowed, its "\r\n", and one byte more to tell it is too long
+	let room = state.limits.line as u64 + 3;
+	let read = match reader.take(room).read_until(b'\n', &mut bytes) { Ok(read) => read, Err(_) => return Err("cannot read line") };
+	if read == 0 { return Ok(None); }
+	state.bytes += read as u64;
+	if state.bytes > state.limits.bytes { return Err(TOO_BIG); }
+	if bytes.last() == Some(&b'\n') { bytes.pop(); if bytes.last() == Some(&b'\r') { bytes.pop(); } }
+	if bytes.len() > state.limits.line { return Err(TOO_LONG); }
+	return String::from_utf8(bytes).map(Some).map_err(|_| "cannot read line");
 }
 
-// NAMECHECK: get names of list in tree and check for duplicates
-fn namecheck (data: Vec<Node>) {
-	let mut names: Vec<String> = recursive_namecheck(data);
-	if names.len() < 2 { println!("OK"); return; }
-	names.sort_by(|a, b| a.cmp(b));
-	let mut duplicates: Vec<String> = vec![];
-	for n in 0..names.len()-1 { if names[n] == names[n+1] { duplicates.push(names[n].clone()); } }
-	duplicates.dedup();
-	if duplicates.len() == 0 { println!("OK"); return; }
-	for n in 0..duplicates.len() {
-		print!("{}", duplicates[n]);
-		if n != duplicates.len()-1 { print!(","); } else { print!("\n"); }
+// splice the data lines of an included file in at this point of the forest
+// 'path' is resolved relative to 'dir' (the including file's directory), and 'stack' holds the
+// canonical paths of the files being read right now, so an include cycle can be reported
+fn include_file (state: &mut InputState, dir: &Path, path: &str, stack: &mut Vec<PathBuf>) -> Result<(), Diagnostic> {
+	let resolved = dir.join(path);
+	let shown = resolved.to_string_lossy().into_owned();
+	if let Ok(canonical) = resolved.canonicalize() {
+		if let Some(start) = stack.iter().position(|p| *p == canonical) {
+			let mut cycle: Vec<String> = stack[start..].iter().map(|p| p.to_string_lossy().into_owned()).collect();
+			cycle.push(canonical.to_string_lossy().into_owned());
+			return Err(Diagnostic::new(&shown, 0, &format!("include cycle: {}", cycle.join(" -> "))));
+		}
 	}
+	return feed_file(state, &shown, Section::Data, stack);
 }
 
-// gather all of the names of lists in the tree
-fn recursive_ptrcheck (data: Vec<Node>) -> Vec<String> {
-	let mut names: Vec<String> = vec![];
-	for node in data {
-		match node.nodetype {
-			Type::List => { 
-				for childstring in recursive_ptrcheck(node.children) {
-					names.push(childstring);
+// FILES: feed every line of a data file or a query file into one section of the input
+// The "." and "QUIT" lines are optional here, but if present they must close the file
+fn feed_file (state: &mut InputState, path: &str, section: Section, stack: &mut Vec<PathBuf>) -> Result<(), Diagnostic> {
+	let file = match std::fs::File::open(path) {
+		Ok(file) => file,
+		Err(e) => return Err(Diagnostic::new(path, 0, &format!("cannot open: {}", e)))
+	};
+	let dir = Path::new(path).parent().unwrap_or(Path::new("")).to_path_buf();
+	stack.push(Path::new(path).canonicalize().unwrap_or(PathBuf::from(path)));
+	let closer = if section == Section::Data { "." } else { "QUIT" };
+	let mut closed = false;
+	let mut reader = io::BufReader::new(file);
+	let mut n = 0;
+	loop {
+		let line = match next_line(state, &mut reader) {
+			Ok(Some(line)) => line,
+			Ok(None) => break,
+			Err(message) => return Err(Diagnostic::new(path, n+1, message))
+		};
+		n += 1;
+		let line = line.trim();
+		if closed { return Err(Diagnostic::new(path, n, &format!("file continues after {}", closer))); }
+		if line == closer { closed = true; continue; }
+		let result = if section == Section::Data {
+			match check_include(line) {
+				Some(Ok(included)) => {
+					if let Err(mut diagnostic) = include_file(state, &dir, &included, stack) {
+						diagnostic.includes.push((path.to_string(), n));
+						return Err(diagnostic);
+					}
+					Ok(())
+				},
+				Some(Err(message)) => Err(message),
+				None => state.add_data(line)
+			}
+		} else { state.add_query(line) };
+		if let Err(message) = result { return Err(Diagnostic::new(path, n, message)); }
+	}
+	stack.pop();
+	return Ok(());
+}
+
+// STDIN: the whole input in one stream, with the "." and "QUIT" lines required
+// INCLUDE lines in the data section are resolved relative to the working directory
+fn feed_stdin (state: &mut InputState) -> Result<(), Diagnostic> {
+	let stdin = io::stdin();
+	let mut reader = stdin.lock();
+	let mut n = 0;
+	loop {
+		let line = match next_line(state, &mut reader) {
+			Ok(Some(line)) => line,
+			Ok(None) => break,
+			Err(message) => return Err(Diagnostic::new("<stdin>", n+1, message))
+		};
+		n += 1;
+		let result = match (state.section, check_include(line.trim())) {
+			(Section::Data, Some(Ok(included))) => {
+				if let Err(mut diagnostic) = include_file(state, Path::new(""), &included, &mut vec![]) {
+					diagnostic.includes.push(("<stdin>".to_string(), n));
+					return Err(diagnostic);
 				}
+				Ok(())
+			},
+			(Section::Data, Some(Err(message))) => Err(message),
+			_ => state.feed(&line)
+		};
+		if let Err(message) = result { return Err(Diagnostic::new("<stdin>", n, message)); }
+	}
+	return Ok(());
+}
+
+// SUM: go through all nodes and get the sum of numeric fields
+// Every traversal borrows the tree, so answering a query never copies it, and keeps the lists
+// still to visit on an explicit stack, so no nesting depth can overflow the call stack
+// 64-bit integers are added up in 128 bits, which no number of them that fits in memory can overflow,
+// and every other NUMBER exactly in decimal; so the total never depends on the order of adding,
+// and with checked arithmetic only a total outside the 64-bit range is an error
+fn tree_sum (data: &[Node], dialect: &Dialect, deadline: Option<Instant>) -> Result<bignum::Decimal, &'static str> {
+	let mut sum: i128 = 0;
+	let mut exact = bignum::Decimal::zero();
+	let mut rest: Vec<&Node> = data.iter().collect();
+	let mut visited: usize = 0;
+	while let Some(node) = rest.pop() {
+		visited += 1;
+		if visited.is_multiple_of(65536) && deadline.map(|d| Instant::now() > d).unwrap_or(false) { return Err(TOO_SLOW); }
+		match node.nodetype  {
+			Type::List | Type::Pair => rest.extend(node.children.iter()),
+			Type::Number => match node.value.parse::<i64>() {
+				Ok(n) => sum += n as i128,
+				Err(_) => exact.add(&bignum::Decimal::parse(&node.value, &dialect.numbers).unwrap())
 			},
-			Type::Pointer => names.push(node.value),
 			_ => {}
 		}
 	}
-	return names;
+	exact.add(&bignum::Decimal::from_i128(sum));
+	if dialect.arithmetic == Arithmetic::Checked && !exact.within_i64() { return Err("SUM does not fit in a 64-bit integer (see --arithmetic big)"); }
+	return Ok(exact);
 }
 
-fn pointercheck (data: Vec<Node>) {
-	// get all the names of the lists
-	let mut names: Vec<String> = recursive_namecheck(data.to_vec());
-	names.sort_by(|a, b| a.cmp(b));
-	names.dedup();
-	// get all the names of all the pointers
-	let mut ptrs: Vec<String> = recursive_ptrcheck(data.to_vec());
-	ptrs.sort_by(|a, b| a.cmp(b));
-	ptrs.dedup();
-	// look for dangling pointers
-	let mut dangling: Vec<String> = vec![];
-	for elem in ptrs {
-		if names.binary_search(&elem).is_err() { dangling.push(elem); }
-	}
-	// print correct message
-	if dangling.len() == 0 { println!("OK"); return; }
-	for n in 0..dangling.len() {
-		print!("{}", dangling[n]);
-		if n != dangling.len()-1 { print!(","); } else { print!("\n"); }
-	}
-}
-
-// recursively search through the tree for a match
-fn recursive_search (data: Vec<Node>, look: &str) -> (Vec<String>,bool,bool) {
-	let mut flag = false;
-	let mut carry: Vec<String> = vec![];
-	let mut childflag = false;
-	for node in data {
+// gather all of the names of lists in the tree, in the order they appear
+fn list_names (data: &[Node]) -> Vec<&str> {
+	let mut names: Vec<&str> = vec![];
+	let mut rest: Vec<&Node> = data.iter().rev().collect();
+	while let Some(node) = rest.pop() {
 		match node.nodetype {
-			Type::List => { 
-				let duple = recursive_search(node.children, look);
-				if duple.1 {
-					if duple.2 { carry.push(node.value.clone()); }
-					let s = ":".to_string() + &*node.value.clone();
-					for each in duple.0 { carry.push(each + &*s); }
-					flag = true;
-				}
-			},
-			Type::ListString => {
-				if node.value == look.to_string() {
-					flag = true;
-					childflag = true;
-				}
-			},
-			Type::Number => {
-				if node.value == look.to_string() {
-					flag = true;
-					childflag = true;
+			Type::List => { names.push(&*node.value); rest.extend(node.children.iter().rev()); },
+			Type::Pair => rest.extend(node.children.iter()),
+			_ => {}
+		}
+	}
+	return names;
+}
+
+// INDEX: built once the data is complete, so NAMECHECK, PTRS and SEARCH are lookups
+// Lists are numbered as they are indexed, and each one remembers the list around it. A list's path
+// is its name followed by the names of the lists around it, innermost first ("inner:outer"), which
+// is how SEARCH reports where it found something; it is only spelled out when it is reported, as
+// the paths of deeply nested lists would take space growing with the square of the depth
+struct Index {
+	// each list's name and the number of the list around it
+	lists: Vec<(String, Option<usize>)>,
+	// list name -> the lists with that name
+	names: BTreeMap<String, Vec<usize>>,
+	// STRING leaf or key -> the lists holding it directly
+	values: HashMap<String, Vec<usize>>,
+	// NUMBER leaf, by the dialect's key for it -> the lists holding it directly
+	// (apart from the STRINGs, so the quoted STRING "1" is not the NUMBER 1)
+	numbers: HashMap<String, Vec<usize>>,
+	// pointer target -> the lists pointing to it
+	pointers: BTreeMap<String, Vec<usize>>,
+	// (list, key) -> the item the key is first given in that list, as GET prints it
+	pairs: HashMap<(usize, String), String>,
+	// each key given more than once in one list, with the list
+	duplicate_keys: Vec<(usize, String)>
+}
+
+impl Index {
+	// NUMBERs are indexed by the dialect's key for them, as SEARCH looks them up
+	fn build (data: &[Node], dialect: &Dialect) -> Index {
+		let mut index = Index { lists: vec![], names: BTreeMap::new(), values: HashMap::new(), numbers: HashMap::new(), pointers: BTreeMap::new(),
+			pairs: HashMap::new(), duplicate_keys: vec![] };
+		// the lists still to index, each with the number of the list around it
+		let mut rest: Vec<(&Node, Option<usize>)> = data.iter().map(|list| (list, None)).collect();
+		while let Some((list, outer)) = rest.pop() {
+			let n = index.lists.len();
+			index.lists.push((list.value.clone(), outer));
+			index.names.entry(list.value.clone()).or_insert(vec![]).push(n);
+			// each leaf, and whether it is a NUMBER
+			let mut leaves: Vec<(bool, Cow<str>)> = vec![];
+			for node in &list.children {
+				// a key is found by SEARCH like a leaf, and its item is indexed as if it stood alone
+				let node = match node.nodetype {
+					Type::Pair => {
+						let item = &node.children[0];
+						let shown = match item.nodetype { Type::Pointer => format!("@{}", item.value), _ => item.value.clone() };
+						match index.pairs.entry((n, node.value.clone())) {
+							Entry::Occupied(_) => index.duplicate_keys.push((n, node.value.clone())),
+							Entry::Vacant(slot) => { slot.insert(shown); }
+						}
+						leaves.push((false, Cow::Borrowed(&*node.value)));
+						item
+					},
+					_ => node
+				};
+				match node.nodetype {
+					Type::List => rest.push((node, Some(n))),
+					Type::Pointer => index.pointers.entry(node.value.clone()).or_insert(vec![]).push(n),
+					Type::Number => leaves.push((true, dialect.number_key(&node.value))),
+					_ => leaves.push((false, Cow::Borrowed(&*node.value)))
 				}
+			}
+			// a list holding the same leaf twice is still found once
+			leaves.sort();
+			leaves.dedup();
+			for (number, leaf) in leaves {
+				let values = if number { &mut index.numbers } else { &mut index.values };
+				values.entry(leaf.into_owned()).or_insert(vec![]).push(n);
+			}
+		}
+		return index;
+	}
+
+	fn path (&self, list: usize) -> String {
+		let mut names: Vec<&str> = vec![];
+		let mut at = Some(list);
+		while let Some(n) = at { names.push(&*self.lists[n].0); at = self.lists[n].1; }
+		return names.join(":");
+	}
+}
+
+// NAMECHECK: the names more than one list has, in order, then the keys given more than once in
+// one list, as the list's path and the key ("inner:outer.key"), in order
+fn namecheck (index: &Index) -> String {
+	let mut duplicates: Vec<String> = index.names.iter().filter(|n| n.1.len() > 1).map(|n| n.0.clone()).collect();
+	let mut keys: Vec<String> = index.duplicate_keys.iter().map(|&(n, ref key)| format!("{}.{}", index.path(n), key)).collect();
+	keys.sort();
+	keys.dedup();
+	duplicates.extend(keys);
+	if duplicates.is_empty() { return "OK".to_string(); }
+	return duplicates.join(",");
+}
+
+// GET: the item each list with the given name gives the key (a LIST item by its name, a pointer
+// with its '@'), in the order of the lists' paths; lists without the key are left out
+fn get (index: &Index, name: &str, key: &str) -> String {
+	let lists = match index.names.get(name) { Some(lists) => lists, None => return "NIL".to_string() };
+	let mut found: Vec<(String, &str)> = lists.iter().filter_map(|&n| index.pairs.get(&(n, key.to_string())).map(|v| (index.path(n), &**v))).collect();
+	if found.is_empty() { return "NIL".to_string(); }
+	found.sort();
+	let items: Vec<&str> = found.iter().map(|f| f.1).collect();
+	return items.join(",");
+}
+
+// PTRS: the pointer targets that name no list, in order
+fn pointercheck (index: &Index) -> String {
+	let dangling: Vec<&str> = index.pointers.keys().filter(|t| !index.names.contains_key(*t)).map(|t| &**t).collect();
+	if dangling.is_empty() { return "OK".to_string(); }
+	return dangling.join(",");
+}
+
+// SEARCH: the lists holding the specified STRING or NUMBER, in alphabetical order
+// a term in quotes is a STRING only in the --quoted dialect; anything not a STRING is looked up as a NUMBER
+fn search(index: &Index, look: &str, dialect: &Dialect) -> String {
+	let quoted = if dialect.quoted { check_quoted(look) } else { None };
+	let found = match (quoted, dialect.strings.check(look)) {
+		(Some((_, text)), _) => index.values.get(&*dialect.text(text)),
+		(None, Some(text)) => index.values.get(&*text),
+		(None, None) => index.numbers.get(&*dialect.number_key(look))
+	};
+	match found {
+		Some(lists) => {
+			let mut found: Vec<String> = lists.iter().map(|&n| index.path(n)).collect();
+			found.sort();
+			return found.join(",");
+		},
+		None => return "NIL".to_string()
+	}
+}
+
+// the answer to a single query against the data tree, as the line it prints
+// fails if the answer is not ready by 'deadline', or cannot be given at all
+fn answer_query(data: &[Node], index: &Index, query: &str, dialect: &Dialect, deadline: Option<Instant>) -> Result<String, &'static str> {
+	let answer = if query == "SUM" || query == "sum" {
+		tree_sum(data, dialect, deadline)?.to_string()
+	} else if query == "NAMECHECK" || query == "namecheck" {
+		namecheck(index)
+	} else if query == "PTRS" || query == "ptrs" {
+		pointercheck(index)
+	} else if let Some(term) = query.strip_prefix("GET ") {
+		// "GET " and NAME.KEY, both already checked STRINGs (so in the form they are stored in)
+		match term.find('.') {
+			Some(at) => get(index, &term[..at], &term[at+1..]),
+			None => return Err("malformed query")
+		}
+	} else {
+		// "SEARCH " and the term, which may be a QSTRING with spaces in it
+		search(index, &query["SEARCH ".len()..], dialect)
+	};
+	if deadline.map(|d| Instant::now() > d).unwrap_or(false) { return Err(TOO_SLOW); }
+	return Ok(answer);
+}
+
+fn usage () -> ! {
+	eprintln!("usage: wls254 [--mode buffered|streaming] [DIALECT] [LIMITS] [--trace] [--cst] [--dot] < input");
+	eprintln!("       wls254 [--mode buffered|streaming] [DIALECT] [LIMITS] [--trace] [--cst] [--dot] [--data FILE]... [--queries FILE]");
+	eprintln!("       wls254 lsp [DIALECT] [LIMITS]");
+	eprintln!("       wls254 parse [--grammar FILE] [--engine backtrack|earley|ll1|lalr|cyk] [--lines] [--tree] [--trace] [--cst] [--dot] [--forest] [--parses N] [--table] < input");
+	eprintln!("       wls254 ll1 [--grammar FILE]");
+	eprintln!("       wls254 lalr [--grammar FILE] [--states]");
+	eprintln!("       wls254 hygiene [--grammar FILE] [--remove-useless] [--remove-left-recursion] [--left-factor]");
+	eprintln!("       wls254 cnf [--grammar FILE]");
+	eprintln!("       wls254 check-engines [--grammar FILE] [--seed N] [--rounds N]");
+	eprintln!("       wls254 generate [DIALECT] [LIMITS] [--seed N] [--depth N] [--width N] [--pointers P] [--duplicates P] [--lines N] [--queries N] [--invalid KIND|any] [--expect FILE] [--diagnostic FILE]");
+	eprintln!("       wls254 generate --grammar FILE [--seed N] [--depth N] [--count N]");
+	eprintln!("       wls254 check-negative [--seed N] [--rounds N]");
+	eprintln!("       wls254 reduce [DIALECT] [LIMITS] [--query Q [--answer TEXT]] [FILE]");
+	eprintln!("       wls254 bench [--bytes N] [--cases wide,string,pointer,deep,queries]");
+	eprintln!("       wls254 strings [FILE]...");
+	eprintln!("       wls254 format [DIALECT] [LIMITS] [FILE]");
+	eprintln!("       wls254 json [DIALECT] [LIMITS] [FILE]...");
+	eprintln!("DIALECT: [--arithmetic checked|big] [--numbers decimal,exponent,hex,separators] [--quoted] [--strings legacy|strict|unicode] [--keys]");
+	eprintln!("LIMITS: [--max-bytes N] [--max-line N] [--max-depth N] [--max-nodes N] [--max-queries N] [--max-time SECONDS]");
+	std::process::exit(2);
+}
+
+// DIALECT and LIMITS flags, taken the same way by every command that reads inputs
+// 'arg' has already been taken from 'args'; false if it is not one of these flags
+fn dialect_flag<I: Iterator<Item=String>> (arg: &str, args: &mut I, dialect: &mut Dialect, limits: &mut Limits) -> bool {
+	match arg {
+		"--arithmetic" => match args.next().as_deref() {
+			Some("checked") => dialect.arithmetic = Arithmetic::Checked,
+			Some("big") => dialect.arithmetic = Arithmetic::Big,
+			_ => usage()
+		},
+		"--numbers" => for notation in args.next().unwrap_or_else(|| usage()).split(',') {
+			match notation {
+				"decimal" => dialect.numbers.decimal = true,
+				"exponent" => dialect.numbers.exponent = true,
+				"hex" => dialect.numbers.hex = true,
+				"separators" => dialect.numbers.separators = true,
+				_ => usage()
+			}
+		},
+		"--quoted" => dialect.quoted = true,
+		"--keys" => dialect.keys = true,
+		"--strings" => match args.next().as_deref() {
+			Some("legacy") => dialect.strings = Strings::Legacy,
+			Some("strict") => dialect.strings = Strings::Strict,
+			Some("unicode") => dialect.strings = Strings::Unicode,
+			_ => usage()
+		},
+		"--max-bytes" | "--max-line" | "--max-depth" | "--max-nodes" | "--max-queries" => {
+			let n = match args.next().and_then(|n| n.parse::<usize>().ok()) { Some(n) if n > 0 => n, _ => usage() };
+			match arg {
+				"--max-bytes" => limits.bytes = n as u64,
+				"--max-line" => limits.line = n,
+				"--max-depth" => limits.depth = n,
+				"--max-nodes" => limits.nodes = n,
+				_ => limits.queries = n
+			}
+		},
+		"--max-time" => match args.next().and_then(|t| t.parse::<f64>().ok()) {
+			Some(t) if t > 0.0 && t < 1e9 => limits.time = Duration::from_millis((t * 1000.0) as u64),
+			_ => usage()
+		},
+		_ => return false
+	}
+	return true;
+}
+
+// RUN: the assignment's program -- read the input, answer the queries or print ERR
+fn run_command (args: &[String]) -> i32 {
+	// read the command line: how query answers are released, and where the input comes from
+	let mut mode = Mode::Buffered;
+	let mut datafiles: Vec<String> = vec![];
+	let mut queryfile: Option<String> = None;
+	let mut views = trace::Views { derivation: false, cst: false, dot: false };
+	let mut dialect = Dialect::new();
+	let mut limits = Limits::new();
+	let mut args = args.iter().cloned();
+	while let Some(arg) = args.next() {
+		match &*arg {
+			"--mode" => match args.next().as_deref() {
+				Some("buffered") => mode = Mode::Buffered,
+				Some("streaming") => mode = Mode::Streaming,
+				_ => usage()
 			},
-			_ => {}
+			"--data" => match args.next() { Some(path) => datafiles.push(path), None => usage() },
+			"--queries" => match args.next() { Some(path) => queryfile = Some(path), None => usage() },
+			"--trace" => views.derivation = true,
+			"--cst" => views.cst = true,
+			"--dot" => views.dot = true,
+			_ => if !dialect_flag(&arg, &mut args, &mut dialect, &mut limits) { usage() }
 		}
 	}
-	return (carry,flag, childflag);
-}
-
-// SEARCH: search through the tree for specified STRING or NUMBER
-// prints out all matches in alphabetical order
-fn search(data: Vec<Node>, look: &str) {
-	let mut found: Vec<String> = recursive_search(data, look).0;
-	found.sort_by(|a, b| a.cmp(b));
-	if found.len() == 0 { println!("NIL"); return; }
-	for n in 0..found.len() {
-		print!("{}", found[n]);
-		if n != found.len()-1 { print!(","); } else { print!("\n"); }
-	}
-}
-
-// AFTER the data tree and queries list is confirmed valid, do the queries
-fn run_queries(data: Vec<Node>, queries: Vec<String>) {
-	for query in queries {
-		if query == "SUM" || query == "sum" {
-			println!("{}",recursive_sum(data.to_vec()));
-		} else if query == "NAMECHECK" || query == "namecheck" {
-			namecheck(data.to_vec());
-		} else if query == "PTRS" || query == "ptrs" {
-			pointercheck(data.to_vec());
-		} else {
-			let squery: Vec<&str> = query.split(char::is_whitespace).collect();
-			search(data.to_vec(), squery[1]);
+
+	// parse the input (lexical analysis) as it arrives, performing queries (output) per the mode
+	// with files, all data files make up one forest and the query file follows them
+	let mut state = InputState::new(mode);
+	state.dialect = dialect;
+	state.limits = limits;
+	if views.any() { state.tracer = Some(trace::Tracer::new(views, dialect.grammar())); }
+	let result = if datafiles.is_empty() && queryfile.is_none() {
+		feed_stdin(&mut state).and_then(|_| state.finish().map_err(|message| Diagnostic::new("<stdin>", 0, message)))
+	} else {
+		let mut result = Ok(());
+		for path in &datafiles {
+			result = feed_file(&mut state, path, Section::Data, &mut vec![]);
+			if result.is_err() { break; }
+		}
+		if result.is_ok() {
+			if let Some(ref path) = queryfile { result = feed_file(&mut state, path, Section::Queries, &mut vec![]); }
 		}
+		state.section = Section::Done;
+		result.and_then(|_| state.finish().map_err(|message| Diagnostic::new("<input>", 0, message)))
+	};
+	if let Err(diagnostic) = result {
+		eprintln!("{}", diagnostic);
+		println!("ERR");
 	}
+	return 0;
 }
 
 fn main () {
-	// get all user input and store into a vector
-	let stdin = io::stdin();
-	let mut v : Vec<String> = vec![];
-	for line in stdin.lock().lines() { v.push(line.unwrap().to_string()); }
-	
-	// parse the input (lexical analysis)
-	let triple = validate_input(v);
-
-	// perform queries (output) if input was valid
-	if triple.2 { run_queries(triple.0, triple.1); } 
-	else { println!("ERR"); }
+	// the first argument may name a subcommand; anything else runs the assignment's program
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	let rest = if !args.is_empty() { &args[1..] } else { &args[..] };
+	let status = match args.first().map(|a| &**a) {
+		Some("lsp") => lsp::serve(rest),
+		Some("parse") => grammar::parse_command(rest),
+		Some("ll1") => ll1::ll1_command(rest),
+		Some("lalr") => lalr::lalr_command(rest),
+		Some("hygiene") => hygiene::hygiene_command(rest),
+		Some("cnf") => cyk::cnf_command(rest),
+		Some("check-engines") => differential::check_engines_command(rest),
+		Some("generate") => generate::generate_command(rest),
+		Some("check-negative") => generate::check_negative_command(rest),
+		Some("reduce") => reduce::reduce_command(rest),
+		Some("bench") => bench::bench_command(rest),
+		Some("strings") => strings::strings_command(rest),
+		Some("format") => export::format_command(rest),
+		Some("json") => export::json_command(rest),
+		_ => run_command(&args)
+	};
+	std::process::exit(status);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// data lines and whether the assignment's program has always accepted them
+	#[test]
+	fn nested_lists_keep_their_verdicts () {
+		let lines = [("{a: 1, {b: 2, {c: 3}}}", true), ("{a: {b: {c: 1}, 2}}", false), ("{a: {b: 1}, {c: 2}}", true),
+			("{a: {b: {c: 1}}, 2}", true), ("{a: {b: {c: 1}, {d: 2}}}", false), ("{a: {b: 1, {c: 2}}, {d: {e: 3}}}", true)];
+		let grammar = grammar::Grammar::data();
+		for &(line, accepted) in &lines {
+			assert_eq!(check_data(line).0, accepted, "check_data on {}", line);
+			assert_eq!(grammar.parse_input(line).is_ok(), accepted, "data.grammar on {}", line);
+		}
+	}
+
+	// the lists SEARCH reports for each term, in the dialect given
+	fn searches (dialect: &Dialect, lines: &[&str], terms: &[&str]) -> Vec<String> {
+		let data: Vec<Node> = lines.iter().map(|line| parse_list(line, dialect, MAX_DEPTH).unwrap().to_node()).collect();
+		let index = Index::build(&data, dialect);
+		return terms.iter().map(|term| search(&index, term, dialect)).collect();
+	}
+
+	#[test]
+	fn search_keeps_quoted_strings_apart_from_numbers () {
+		let mut dialect = Dialect::new();
+		assert_eq!(searches(&dialect, &["{a: 1}", "{b: abc}"], &["1", "abc", "\"abc\""]), ["a", "b", "NIL"]);
+		dialect.quoted = true;
+		assert_eq!(searches(&dialect, &["{a: 1}", "{b: \"1\", \"abc\"}"], &["1", "\"1\"", "abc", "\"abc\""]), ["a", "b", "b", "b"]);
+	}
+
+	#[test]
+	fn query_limit_counts_without_keeping_streamed_queries () {
+		for &mode in &[Mode::Buffered, Mode::Streaming] {
+			let mut state = InputState::new(mode);
+			state.limits.queries = 2;
+			for line in &["{a: 1}", ".", "SUM", "SUM"] { state.feed(line).unwrap(); }
+			assert_eq!(state.queries.len(), if mode == Mode::Buffered { 2 } else { 0 });
+			assert_eq!(state.feed("SUM"), Err(TOO_MANY_QUERIES));
+		}
+	}
+
+	#[test]
+	fn data_file_continuing_after_the_separator_names_that_line () {
+		let path = std::env::temp_dir().join(format!("wls254-test-{}.txt", std::process::id()));
+		std::fs::write(&path, "{a: 1}\n.\n{b: 2}\n").unwrap();
+		let path = path.to_str().unwrap().to_string();
+		let result = feed_file(&mut InputState::new(Mode::Buffered), &path, Section::Data, &mut vec![]);
+		std::fs::remove_file(&path).unwrap();
+		let diagnostic = result.err().unwrap();
+		assert_eq!((diagnostic.line, &*diagnostic.message), (3, "file continues after ."));
+	}
+
+	// the answers the program prints for a whole input in 'dialect', or the reason it rejects it
+	fn run (dialect: Dialect, lines: &[&str]) -> Result<Vec<String>, &'static str> {
+		let mut state = InputState::new(Mode::Buffered);
+		state.dialect = dialect;
+		for line in lines { state.feed(line)?; }
+		state.ended()?;
+		let index = Index::build(&state.datatree, &dialect);
+		return state.queries.iter().map(|query| answer_query(&state.datatree, &index, query, &dialect, None)).collect();
+	}
+
+	#[test]
+	fn input_is_checked_a_line_at_a_time () {
+		let dialect = Dialect::new();
+		assert_eq!(run(dialect, &["{a: 1, {b: 2}}", "  .  ", "SUM", "search 2", "QUIT"]), Ok(vec!["3".to_string(), "b:a".to_string()]));
+		assert_eq!(run(dialect, &["{a: 1}", ".", "SUM"]), Err("input ended before QUIT"));
+		assert_eq!(run(dialect, &["{a: 1}", ".", "QUIT", "SUM"]), Err("input continues after QUIT"));
+		// a bad line is found as it is fed, before the rest of the input is read
+		let mut state = InputState::new(Mode::Streaming);
+		assert_eq!(state.feed("{a: 1"), Err(MALFORMED));
+		// line endings go, other whitespace stays for the line's own checks
+		let mut reader = io::Cursor::new(&b"{a: 1}\r\n .\nSUM"[..]);
+		let lines: Vec<Option<String>> = (0..4).map(|_| next_line(&mut state, &mut reader).unwrap()).collect();
+		assert_eq!(lines, [Some("{a: 1}".to_string()), Some(" .".to_string()), Some("SUM".to_string()), None]);
+	}
+
+	// a fresh directory holding the given files, for the tests that read inputs from files
+	fn files (test: &str, files: &[(&str, &str)]) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("wls254-{}-{}", test, std::process::id()));
+		let _ = std::fs::remove_dir_all(&dir);
+		for &(name, text) in files {
+			let path = dir.join(name);
+			std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+			std::fs::write(path, text).unwrap();
+		}
+		return dir;
+	}
+
+	#[test]
+	fn data_and_queries_come_from_their_own_files () {
+		let dir = files("files", &[("a.txt", "{a: 1}\n"), ("b.txt", "{b: 2}\n.\n"), ("q.txt", "SUM\nptrs\nQUIT\n"), ("bad.txt", "SUM\n{c: 3}\n")]);
+		let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+		let mut state = InputState::new(Mode::Buffered);
+		for name in &["a.txt", "b.txt"] { assert!(feed_file(&mut state, &path(name), Section::Data, &mut vec![]).is_ok()); }
+		assert!(feed_file(&mut state, &path("q.txt"), Section::Queries, &mut vec![]).is_ok());
+		assert_eq!((state.datatree.len(), &state.queries[..]), (2, &["SUM".to_string(), "PTRS".to_string()][..]));
+		let diagnostic = feed_file(&mut state, &path("bad.txt"), Section::Queries, &mut vec![]).err().unwrap();
+		assert_eq!(format!("{}", diagnostic), format!("{}:2: malformed query", path("bad.txt")));
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn includes_splice_data_in_and_report_cycles () {
+		let dir = files("include", &[("main.txt", "{a: 1}\nINCLUDE \"lib/x.txt\"\n"), ("lib/x.txt", "{x: @a}\nINCLUDE \"y.txt\"\n"), ("lib/y.txt", "{y: 2}\n"),
+			("loop.txt", "{l: 1}\nINCLUDE \"lib/back.txt\"\n"), ("lib/back.txt", "INCLUDE  \"../loop.txt\"\n")]);
+		let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+		let mut state = InputState::new(Mode::Buffered);
+		assert!(feed_file(&mut state, &path("main.txt"), Section::Data, &mut vec![]).is_ok());
+		let names: Vec<&str> = state.datatree.iter().map(|list| &*list.value).collect();
+		assert_eq!(names, ["a", "x", "y"]);
+		let diagnostic = feed_file(&mut InputState::new(Mode::Buffered), &path("loop.txt"), Section::Data, &mut vec![]).err().unwrap();
+		assert!(diagnostic.message.starts_with("include cycle: "), "{}", diagnostic);
+		assert_eq!(diagnostic.includes, [(path("lib/back.txt"), 1), (path("loop.txt"), 2)]);
+		assert_eq!(check_include("INCLUDE \"\""), Some(Err("malformed INCLUDE")));
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn data_lines_borrow_from_the_line () {
+		let mut dialect = Dialect::new();
+		dialect.quoted = true;
+		let line = "{a: 1, x, @b, \"p q\", \"r\\ts\", {c: y}}";
+		let list = parse_list(line, &dialect, MAX_DEPTH).unwrap();
+		let borrowed = |text: &Cow<str>| match *text { Cow::Borrowed(_) => true, Cow::Owned(_) => false };
+		assert!(borrowed(&list.name));
+		let kinds: Vec<(&str, bool)> = list.items.iter().map(|item| match *item {
+			Item::Number(text) => (text, true),
+			Item::Str(ref text) | Item::Pointer(ref text) => (&**text, borrowed(text)),
+			Item::List(ref inner) => (&*inner.name, borrowed(&inner.name)),
+			Item::Pair(..) => ("pair", false)
+		}).collect();
+		// only a quoted STRING with an escape in it has text of its own
+		assert_eq!(kinds, [("1", true), ("x", true), ("b", true), ("p q", true), ("r\ts", false), ("c", true)]);
+		assert_eq!(list.size(), 8);
+		let node = list.to_node();
+		assert_eq!((&*node.value, node.children.len(), &*node.children[4].value), ("a", 6, "r\ts"));
+	}
+
+	#[test]
+	fn queries_answer_over_the_whole_forest () {
+		let lines = ["{a: 1, x, @c, {b: x, @a}}", "{c: 2, {a: x}}", "{d: @zz, -3, @yy, @zz}", ".", "SUM", "PTRS", "NAMECHECK", "SEARCH x", "search -3", "SEARCH q", "QUIT"];
+		assert_eq!(run(Dialect::new(), &lines).unwrap(), ["0", "yy,zz", "a", "a,a:c,b:a", "d", "NIL"]);
+	}
+
+	#[test]
+	fn the_index_holds_each_list_once_per_leaf () {
+		let dialect = Dialect::new();
+		let data: Vec<Node> = ["{a: x, x, @b, {b: 1, @b}}", "{b: x}"].iter().map(|line| check_data(line).1).collect();
+		let index = Index::build(&data, &dialect);
+		let paths = |lists: &Vec<usize>| -> Vec<String> {
+			let mut paths: Vec<String> = lists.iter().map(|&n| index.path(n)).collect();
+			paths.sort();
+			return paths;
+		};
+		assert_eq!((paths(&index.names["b"]), paths(&index.values["x"]), paths(&index.pointers["b"])),
+			(vec!["b".to_string(), "b:a".to_string()], vec!["a".to_string(), "b".to_string()], vec!["a".to_string(), "b:a".to_string()]));
+		assert_eq!(paths(&index.numbers["1"]), ["b:a"]);
+		assert_eq!((namecheck(&index), pointercheck(&index), search(&index, "x", &dialect)), ("b".to_string(), "OK".to_string(), "a,b".to_string()));
+	}
+
+	#[test]
+	fn inputs_past_the_limits_are_refused () {
+		let mut state = InputState::new(Mode::Buffered);
+		state.limits.line = 6;
+		state.limits.bytes = 24;
+		let mut reader = io::Cursor::new(&b"{a: 1}\r\n{bb: 22}\n{a: 1}\n{a: 1}\n"[..]);
+		assert_eq!(next_line(&mut state, &mut reader), Ok(Some("{a: 1}".to_string())));
+		assert_eq!(next_line(&mut state, &mut reader), Err(TOO_LONG));
+		assert_eq!(next_line(&mut state, &mut reader), Ok(Some("{a: 1}".to_string())));
+		assert_eq!(next_line(&mut state, &mut reader), Err(TOO_BIG));
+		let mut state = InputState::new(Mode::Buffered);
+		state.limits.depth = 2;
+		state.limits.nodes = 5;
+		assert_eq!(state.feed("{a: {b: {c: 1}}}"), Err(TOO_DEEP));
+		assert_eq!(state.feed("{a: 1, {b: 2}}"), Ok(()));
+		assert_eq!(state.feed("{c: 3}"), Err(TOO_MANY_NODES));
+		let mut state = InputState::new(Mode::Buffered);
+		state.limits.time = Duration::from_secs(0);
+		for line in &["{a: 1}", ".", "SUM", "QUIT"] { state.feed(line).unwrap(); }
+		assert_eq!(state.finish(), Err(TOO_SLOW));
+	}
+
+	#[test]
+	fn sum_is_checked_or_exact () {
+		let lines = ["{a: 9223372036854775807, {b: 1}}", ".", "SUM", "QUIT"];
+		let mut dialect = Dialect::new();
+		assert_eq!(run(dialect, &lines), Err("SUM does not fit in a 64-bit integer (see --arithmetic big)"));
+		assert_eq!(run(dialect, &["{a: 99999999999999999999}", ".", "QUIT"]), Err(MALFORMED));
+		dialect.arithmetic = Arithmetic::Big;
+		assert_eq!(run(dialect, &lines), Ok(vec!["9223372036854775808".to_string()]));
+		assert_eq!(run(dialect, &["{a: 99999999999999999999, -100000000000000000000}", ".", "SUM", "SEARCH 99999999999999999999", "QUIT"]),
+			Ok(vec!["-1".to_string(), "a".to_string()]));
+	}
+
+	#[test]
+	fn numbers_in_other_notations_sum_exactly_and_search_by_value () {
+		let lines = ["{a: 0.1, 0.2, {b: 1e2, 0x10, 1_000}}", ".", "SUM", "SEARCH 100", "search 16.0", "SEARCH 1000", "QUIT"];
+		let mut dialect = Dialect::new();
+		assert_eq!(run(dialect, &lines), Err(MALFORMED));
+		dialect.numbers = bignum::Notation { decimal: true, exponent: true, hex: true, separators: true };
+		assert_eq!(run(dialect, &lines).unwrap(), ["1116.3", "b:a", "b:a", "b:a"]);
+		dialect.numbers.hex = false;
+		assert_eq!(run(dialect, &lines), Err(MALFORMED));
+	}
+
+	#[test]
+	fn strings_follow_the_chosen_class () {
+		let lines = ["{cafe\u{301}: x}", "{b: @caf\u{e9}, y_1}", ".", "SEARCH x", "PTRS", "SEARCH y_1", "QUIT"];
+		let mut dialect = Dialect::new();
+		assert_eq!(run(dialect, &lines), Err(MALFORMED));
+		dialect.strings = Strings::Unicode;
+		// the list name is kept composed, as the pointer to it is written
+		assert_eq!(run(dialect, &lines).unwrap(), ["caf\u{e9}", "OK", "b"]);
+		dialect.strings = Strings::Strict;
+		assert_eq!(run(dialect, &["{a: y_1}", ".", "QUIT"]), Err(MALFORMED));
+		assert_eq!((Strings::Legacy.check("_a^"), Strings::Strict.check("a1"), Strings::Unicode.check("1a")), (Some(Cow::Borrowed("_a^")), Some(Cow::Borrowed("a1")), None));
+	}
+
+	#[test]
+	fn search_takes_one_whole_term () {
+		let mut dialect = Dialect::new();
+		for query in &["search {", "SEARCH a.b", "find 1", "SUM 1"] { assert!(!parse_query(query, &dialect).0, "{}", query); }
+		assert_eq!((parse_query("search 1", &dialect).1, parse_query("SEARCH ab", &dialect).1), ("SEARCH 1".to_string(), "SEARCH ab".to_string()));
+		dialect.quoted = true;
+		assert_eq!(parse_query("SEARCH \"a b\"", &dialect).1, "SEARCH \"a b\"");
+		for query in &["SEARCH \"a\"b", "search \"a\" b", "SEARCH \"a"] { assert!(!parse_query(query, &dialect).0, "{}", query); }
+	}
+
+	#[test]
+	fn keys_name_items_for_get_search_and_namecheck () {
+		let lines = ["{s: k={t: 1, v=2}, {x: \"a b\", k = @s}}", "{d: k=1, k=2, 3}", ".", "GET s.k", "GET t.v", "GET x.k", "get d.k", "GET d.q", "SEARCH k", "SUM", "NAMECHECK", "QUIT"];
+		let mut dialect = Dialect::new();
+		dialect.quoted = true;
+		assert_eq!(run(dialect, &lines), Err(MALFORMED));
+		assert_eq!(run(dialect, &["{a: 1}", ".", "GET a.b", "QUIT"]), Err("malformed query"));
+		dialect.keys = true;
+		assert_eq!(run(dialect, &lines).unwrap(), ["t", "2", "@s", "1", "NIL", "d,s,x:s", "9", "d.k"]);
+		assert_eq!(run(dialect, &["{a: k=j=1}", ".", "QUIT"]), Err(MALFORMED));
+		assert_eq!(answer_query(&[], &Index::build(&[], &dialect), "GET ab", &dialect, None), Err("malformed query"));
+	}
 }
@@ -0,0 +1,11 @@
+// The language server as a binary of its own, for editors that start a server by its path:
+//   rustc --edition 2015 -O wls254_lsp.rs
+// It is the same server as 'wls254 lsp' and takes the same flags: wls254_lsp [DIALECT] [LIMITS]
+#[path = "wls254.rs"]
+#[allow(dead_code)]
+mod wls254;
+
+fn main () {
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	std::process::exit(wls254::lsp::serve(&args));
+}